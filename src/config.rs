@@ -0,0 +1,200 @@
+// Site-wide settings, read from a ferne.toml (by default the one in the working
+// directory). Unlike a __common.toml, nothing in it is merged into the config of the
+// pages: templates see the settings as site.*, along with the navigation tree.
+//
+//   source = "src"          # relative to ferne.toml, overridden by --source
+//   destination = "build"   # likewise, overridden by --destination
+//   base_url = "https://example.org"
+//   title = "Student Probability Seminar"
+//   language = "en"
+//
+//   [output]
+//   robots = true           # like --robots
+//   pretty_urls = true      # write foo.md to foo/index.html, see walker::site
+//   redirects = true        # write a _redirects file, see walker::aliases
+//
+//   [theme]                 # the default theme, as in the theme table of a __common.toml
+//   path = "theme.hbs"
+//
+// Any other keys are available to templates too.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+use crate::{assert_toml_kind, util};
+
+pub const CONFIG_FILE: &str = "./ferne.toml";
+
+const SOURCE_KEY: &str = "source";
+const DESTINATION_KEY: &str = "destination";
+pub const BASE_URL_KEY: &str = "base_url";
+const OUTPUT_TABLE_KEY: &str = "output";
+const ROBOTS_KEY: &str = "robots";
+pub const PRETTY_URLS_KEY: &str = "pretty_urls";
+const REDIRECTS_KEY: &str = "redirects";
+pub const THEME_TABLE_KEY: &str = "theme";
+
+#[derive(Clone, Debug, Default)]
+pub struct SiteConfig {
+    pub source: Option<PathBuf>,
+    pub destination: Option<PathBuf>,
+    pub base_url: Option<String>,
+
+    pub robots: bool,
+    pub pretty_urls: bool,
+    pub redirects: bool,
+
+    pub theme: toml::Table,    // the theme table of the root directory
+    pub settings: toml::Table, // everything templates see as site.*
+    pub fingerprint: String,   // of the whole file, since pages depend on it
+}
+
+#[allow(clippy::needless_arbitrary_self_type)]
+impl SiteConfig {
+    // A missing file is the same as an empty one
+    pub async fn load(path: &PathBuf) -> Result<Self> {
+        let (table, contents) = util::toml::read(path).await?;
+
+        // paths in the file are relative to it
+        let root = path.parent().map(PathBuf::from).unwrap_or_default();
+
+        let mut config = Self::from_toml(table, &root)
+            .context(format!("Invalid site config in `{}`.", path.display()))?;
+        config.fingerprint = util::hash::fingerprint(contents.as_bytes());
+
+        Ok(config)
+    }
+
+    fn from_toml(mut table: toml::Table, root: &Path) -> Result<Self> {
+        let source = assert_toml_kind!(String; table, SOURCE_KEY)?.map(|source| root.join(source));
+        let destination = assert_toml_kind!(String; table, DESTINATION_KEY)?
+            .map(|destination| root.join(destination));
+        let base_url = assert_toml_kind!(String; table, BASE_URL_KEY)?;
+
+        let output = assert_toml_kind!(Table; table, OUTPUT_TABLE_KEY)?.unwrap_or_default();
+        let robots = assert_toml_kind!(Boolean; output, ROBOTS_KEY)?.unwrap_or(false);
+        let pretty_urls = assert_toml_kind!(Boolean; output, PRETTY_URLS_KEY)?.unwrap_or(false);
+        let redirects = assert_toml_kind!(Boolean; output, REDIRECTS_KEY)?.unwrap_or(false);
+
+        let theme = assert_toml_kind!(Table; table, THEME_TABLE_KEY)?.unwrap_or_default();
+
+        // the base url is seen by templates (as resolved with --base-url, see walker::render)
+        for key in [
+            SOURCE_KEY,
+            DESTINATION_KEY,
+            OUTPUT_TABLE_KEY,
+            THEME_TABLE_KEY,
+        ] {
+            table.remove(key);
+        }
+
+        Ok(SiteConfig {
+            source,
+            destination,
+            base_url,
+            robots,
+            pretty_urls,
+            redirects,
+            theme,
+            settings: table,
+            fingerprint: String::new(),
+        })
+    }
+
+    // The config the root directory starts from, before its __common.toml
+    pub fn root_table(self: &Self) -> toml::Table {
+        let mut table = toml::Table::new();
+        if self.pretty_urls {
+            // directories can still turn it off with pretty_urls = false
+            table.insert(PRETTY_URLS_KEY.to_owned(), toml::Value::Boolean(true));
+        }
+        if !self.theme.is_empty() {
+            table.insert(
+                THEME_TABLE_KEY.to_owned(),
+                toml::Value::Table(self.theme.clone()),
+            );
+        }
+        table
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(text: &str) -> Result<SiteConfig> {
+        SiteConfig::from_toml(toml::from_str(text).unwrap(), Path::new("site"))
+    }
+
+    #[test]
+    fn settings() {
+        let config = config(
+            r#"
+            source = "src"
+            destination = "../public"
+            base_url = "https://example.org"
+            title = "Seminar"
+
+            [output]
+            robots = true
+            pretty_urls = true
+
+            [theme]
+            path = "theme.hbs"
+            "#,
+        )
+        .unwrap();
+
+        // paths are relative to the config file
+        assert_eq!(config.source, Some(PathBuf::from("site/src")));
+        assert_eq!(config.destination, Some(PathBuf::from("site/../public")));
+        assert_eq!(config.base_url.as_deref(), Some("https://example.org"));
+        assert!(config.robots && config.pretty_urls && !config.redirects);
+        assert_eq!(config.theme["path"].as_str(), Some("theme.hbs"));
+
+        // templates see the rest, and the base url
+        let mut keys = config.settings.keys().collect::<Vec<_>>();
+        keys.sort();
+        assert_eq!(keys, vec!["base_url", "title"]);
+    }
+
+    #[test]
+    fn defaults() {
+        let config = config("").unwrap();
+        assert!(config.source.is_none() && config.destination.is_none());
+        assert!(config.base_url.is_none());
+        assert!(!config.robots && !config.pretty_urls && !config.redirects);
+        assert!(config.root_table().is_empty());
+    }
+
+    #[test]
+    fn root_table() {
+        let config = config("[output]\npretty_urls = true\n[theme]\nname = \"dept\"").unwrap();
+        let table = config.root_table();
+        assert_eq!(table[PRETTY_URLS_KEY].as_bool(), Some(true));
+        assert_eq!(table[THEME_TABLE_KEY]["name"].as_str(), Some("dept"));
+    }
+
+    #[test]
+    fn wrong_types() {
+        for text in [
+            "source = 1",
+            "base_url = true",
+            "output = 1",
+            "[output]\nrobots = \"yes\"",
+            "theme = \"dept\"",
+        ] {
+            assert!(config(text).is_err(), "`{}` was accepted", text);
+        }
+    }
+
+    #[tokio::test]
+    async fn missing_files_are_empty() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = SiteConfig::load(&dir.path().join("ferne.toml"))
+            .await
+            .unwrap();
+        assert!(config.settings.is_empty());
+    }
+}
@@ -0,0 +1,467 @@
+// Helpers available to every theme and markdown file, on top of the ones built
+// into handlebars (which already has eq, ne, lt, lte, gt, gte, and, or, not and len).
+//
+//   {{format_date page.config.date format="%d %b %Y"}}   format a date, see util::date,
+//                                                         the format may also come second
+//   {{slugify title}}                                     url-friendly name, see util::slug
+//   {{{markdownify abstract}}}                            inline markdown of a string
+//   {{truncate text 80 ellipsis="..."}}                   at most that many characters
+//   {{{to_json value pretty=true}}}                       the value as json
+//   {{default_to subtitle "Untitled"}}                    the fallback if null or empty
+//
+// None of them is named like a common field (date, json, default...), since a
+// helper takes the place of the field of that name in {{date}}.
+//
+// and for arrays, where key is a (dotted) path into each item:
+//
+//   {{#each (sort_by sessions "date" reverse=true)}}
+//   {{#each (where_eq sessions "room" "A1")}}
+//   {{#each (group_by sessions "date")}} {{key}}: {{#each items}}...{{/each}}
+//
+// so that group_by gives the items sharing a value of the key, as { key, items },
+// in the order in which the keys first appear.
+//
+// Links are made relative to the page being rendered, so that a theme works for
+// pages at any depth:
+//
+//   {{url_for "seminar/index.md"}}     the page written from that source
+//   {{relative_url page.url}}          a url of the site, such as those in site.root
+//   {{asset_url "assets/style.css"}}   a file copied to the destination as is
+
+use std::cmp::Ordering;
+
+use chrono::format::{Item, StrftimeItems};
+use handlebars::{
+    handlebars_helper, Context, Handlebars, Helper, HelperDef, RenderContext, RenderError,
+    RenderErrorReason, ScopedJson,
+};
+use serde_json::{json, Value};
+
+use crate::{
+    theme::{SITE_SLOT, URL_SLOT},
+    util,
+    walker::{compare, ROOT_KEY},
+};
+
+const DEFAULT_DATE_FORMAT: &str = "%B %-d, %Y";
+
+pub fn register(hb: &mut Handlebars) {
+    hb.register_helper("format_date", Box::new(DateHelper));
+    hb.register_helper("slugify", Box::new(slugify));
+    hb.register_helper("markdownify", Box::new(markdownify));
+    hb.register_helper("truncate", Box::new(truncate));
+    hb.register_helper("to_json", Box::new(to_json));
+    hb.register_helper("default_to", Box::new(default_to));
+    hb.register_helper("sort_by", Box::new(sort_by));
+    hb.register_helper("where_eq", Box::new(where_eq));
+    hb.register_helper("group_by", Box::new(group_by));
+    hb.register_helper("url_for", Box::new(UrlHelper::Source));
+    hb.register_helper("relative_url", Box::new(UrlHelper::Url));
+    hb.register_helper("asset_url", Box::new(UrlHelper::Asset));
+}
+
+// A date from a string (or toml datetime), formatted with a chrono format string.
+// Unlike the others this fails the render on a bad date, instead of printing it.
+struct DateHelper;
+
+impl HelperDef for DateHelper {
+    fn call_inner<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'rc>,
+        _: &'reg Handlebars<'reg>,
+        _: &'rc Context,
+        _: &mut RenderContext<'reg, 'rc>,
+    ) -> Result<ScopedJson<'rc>, RenderError> {
+        let text = h.param(0).and_then(|param| param.value().as_str()).ok_or(
+            RenderErrorReason::ParamTypeMismatchForName(
+                "format_date",
+                "value".to_owned(),
+                "str".to_owned(),
+            ),
+        )?;
+
+        // the format is given as format="...", or as a second parameter
+        let format = match h.hash_get("format").or(h.param(1)) {
+            Some(format) => {
+                format
+                    .value()
+                    .as_str()
+                    .ok_or(RenderErrorReason::HashTypeMismatchForName(
+                        "format_date",
+                        "format".to_owned(),
+                        "str".to_owned(),
+                    ))?
+            }
+            None => DEFAULT_DATE_FORMAT,
+        };
+
+        let date = util::date::parse(text)
+            .map_err(|err| RenderErrorReason::Other(format!("{:#}", err)))?;
+
+        // chrono panics when it writes out a bad format, so check it first
+        let items = StrftimeItems::new(format).collect::<Vec<_>>();
+        if items.contains(&Item::Error) {
+            return Err(
+                RenderErrorReason::Other(format!("Invalid date format `{}`.", format)).into(),
+            );
+        }
+
+        Ok(ScopedJson::Derived(Value::String(
+            date.format_with_items(items.into_iter()).to_string(),
+        )))
+    }
+}
+
+handlebars_helper!(slugify: |text: str| util::slug::slugify(text));
+
+handlebars_helper!(markdownify: |text: str| util::markdown::to_inline_html(text));
+
+handlebars_helper!(truncate: |text: str, length: u64, { ellipsis: str = "…" }| {
+    if text.chars().count() as u64 <= length {
+        text.to_owned()
+    } else {
+        let kept = text.chars().take(length as usize).collect::<String>();
+        format!("{}{}", kept.trim_end(), ellipsis)
+    }
+});
+
+handlebars_helper!(to_json: |value: Json, { pretty: bool = false }| {
+    let text = if pretty {
+        serde_json::to_string_pretty(value)
+    } else {
+        serde_json::to_string(value)
+    };
+    text.unwrap_or_default()
+});
+
+handlebars_helper!(default_to: |value: Json, fallback: Json| {
+    match value {
+        Value::Null => fallback.clone(),
+        Value::String(text) if text.is_empty() => fallback.clone(),
+        _ => value.clone(),
+    }
+});
+
+// items missing the key come last, in their previous order, like walker::site::sort
+handlebars_helper!(sort_by: |items: array, key: str, { reverse: bool = false }| {
+    let mut items = items.clone();
+    items.sort_by(|a, b| match (lookup(a, key), lookup(b, key)) {
+        (Some(a_), Some(b_)) if reverse => compare(b_, a_),
+        (Some(a_), Some(b_)) => compare(a_, b_),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    });
+    items
+});
+
+handlebars_helper!(where_eq: |items: array, key: str, value: Json| {
+    items
+        .iter()
+        .filter(|item| lookup(item, key) == Some(value))
+        .cloned()
+        .collect::<Vec<_>>()
+});
+
+handlebars_helper!(group_by: |items: array, key: str| {
+    let mut groups: Vec<(Value, Vec<Value>)> = vec![];
+
+    for item in items {
+        let value = lookup(item, key).cloned().unwrap_or(Value::Null);
+        match groups.iter_mut().find(|(value_, _)| *value_ == value) {
+            Some((_, group)) => group.push(item.clone()),
+            None => groups.push((value, vec![item.clone()])),
+        }
+    }
+
+    groups
+        .into_iter()
+        .map(|(value, group)| json!({ "key": value, "items": group }))
+        .collect::<Vec<_>>()
+});
+
+// The link to a page, url or asset, relative to the url of the page being rendered
+enum UrlHelper {
+    Source,
+    Url,
+    Asset,
+}
+
+impl HelperDef for UrlHelper {
+    fn call_inner<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'rc>,
+        _: &'reg Handlebars<'reg>,
+        ctx: &'rc Context,
+        _: &mut RenderContext<'reg, 'rc>,
+    ) -> Result<ScopedJson<'rc>, RenderError> {
+        let name = match self {
+            UrlHelper::Source => "url_for",
+            UrlHelper::Url => "relative_url",
+            UrlHelper::Asset => "asset_url",
+        };
+        // directories without an index page have no url in the navigation tree
+        let value = h.param(0).map(|param| param.value());
+        if matches!(self, UrlHelper::Url) && value.is_some_and(Value::is_null) {
+            return Ok(ScopedJson::Derived(Value::String(String::new())));
+        }
+
+        let target =
+            value
+                .and_then(Value::as_str)
+                .ok_or(RenderErrorReason::ParamTypeMismatchForName(
+                    name,
+                    "path".to_owned(),
+                    "str".to_owned(),
+                ))?;
+
+        let url = match self {
+            UrlHelper::Source => {
+                let root = &ctx.data()[SITE_SLOT][ROOT_KEY];
+                source_url(root, target).ok_or(RenderErrorReason::Other(format!(
+                    "No page is written from `{}`.",
+                    target
+                )))?
+            }
+            UrlHelper::Url => target.to_owned(),
+            UrlHelper::Asset => format!("/{}", target.trim_start_matches('/')),
+        };
+
+        // urls of other sites are left as they are
+        let current = ctx.data()[URL_SLOT].as_str().unwrap_or("/");
+        let link = if url.starts_with('/') && !url.starts_with("//") {
+            relative(current, &url)
+        } else {
+            url
+        };
+
+        Ok(ScopedJson::Derived(Value::String(link)))
+    }
+}
+
+// The url of the page written from a source path, found in the navigation tree (see
+// walker::site). A directory, or its index page, stands for the url of the index.
+fn source_url(root: &Value, source: &str) -> Option<String> {
+    let source = without_extension(source.trim_matches('/'));
+    let source = match source.rsplit_once('/') {
+        Some((dir, "index")) => dir,
+        None if source == "index" => "",
+        _ => source,
+    };
+
+    let mut entries = vec![root];
+    while let Some(entry) = entries.pop() {
+        let entry_source = entry["source"].as_str().unwrap_or_default();
+        let is_dir = entry.get("children").is_some();
+
+        if entry_source == source || (!is_dir && without_extension(entry_source) == source) {
+            return entry["url"].as_str().map(str::to_owned);
+        }
+
+        if let Some(Value::Array(children)) = entry.get("children") {
+            entries.extend(children.iter());
+        }
+    }
+
+    None
+}
+
+fn without_extension(path: &str) -> &str {
+    let name_start = path.rfind('/').map_or(0, |idx| idx + 1);
+    match path[name_start..].rfind('.') {
+        Some(idx) if idx > 0 => &path[..name_start + idx],
+        _ => path,
+    }
+}
+
+// The link from the page at one url of the site to another url of the site
+fn relative(from: &str, to: &str) -> String {
+    let from_dirs = from
+        .rsplit_once('/')
+        .map_or("", |(dir, _)| dir)
+        .split('/')
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>();
+    let to_parts = to.trim_start_matches('/').split('/').collect::<Vec<_>>();
+
+    // the last part of the target is a file name (or empty), never a directory
+    let common = from_dirs
+        .iter()
+        .zip(to_parts[..to_parts.len() - 1].iter())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let link = format!(
+        "{}{}",
+        "../".repeat(from_dirs.len() - common),
+        to_parts[common..].join("/")
+    );
+
+    if link.is_empty() {
+        "./".to_owned()
+    } else {
+        link
+    }
+}
+
+// The value at a dotted path like config.date inside an item
+fn lookup<'a>(item: &'a Value, key: &str) -> Option<&'a Value> {
+    key.split('.')
+        .try_fold(item, |value, part| value.get(part))
+        .filter(|value| !value.is_null())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(template: &str, data: &Value) -> String {
+        let mut hb = Handlebars::new();
+        register(&mut hb);
+        hb.render_template(template, data).unwrap()
+    }
+
+    #[test]
+    fn helpers_leave_fields_alone() {
+        let data = json!({
+            "date": "2024-01-02",
+            "json": "j",
+            "default": "d",
+            "where": "w",
+            "asset": "a",
+        });
+        assert_eq!(render("{{date}}", &data), "2024-01-02");
+        assert_eq!(
+            render("{{json}} {{default}} {{where}} {{asset}}", &data),
+            "j d w a"
+        );
+    }
+
+    #[test]
+    fn relative_links() {
+        // from the root
+        assert_eq!(relative("/", "/"), "./");
+        assert_eq!(relative("/", "/a/b.html"), "a/b.html");
+        assert_eq!(relative("/index.html", "/p.html"), "p.html");
+        // to the root
+        assert_eq!(relative("/a/b.html", "/"), "../");
+        assert_eq!(relative("/a/b/c.html", "/index.html"), "../../index.html");
+        // pretty urls, where a page is its own directory
+        assert_eq!(relative("/seminar/", "/seminar/"), "./");
+        assert_eq!(relative("/seminar/t5/", "/seminar/"), "../");
+        assert_eq!(relative("/seminar/", "/talks/2024/"), "../talks/2024/");
+        assert_eq!(relative("/seminar/", "/seminar/t5/"), "t5/");
+        // files sharing a prefix with a directory
+        assert_eq!(relative("/ab/c.html", "/a/c.html"), "../a/c.html");
+    }
+
+    #[test]
+    fn url_helpers_are_relative_to_the_page() {
+        let data = json!({ "__url__": "/seminar/t5/" });
+        assert_eq!(
+            render("{{asset_url \"/assets/style.css\"}}", &data),
+            "../../assets/style.css"
+        );
+        assert_eq!(render("{{relative_url \"/p/\"}}", &data), "../../p/");
+        assert_eq!(render("{{relative_url null}}", &data), "");
+        // links to other sites are left alone
+        assert_eq!(
+            render("{{relative_url \"https://x.org/a\"}}", &data),
+            "https://x.org/a"
+        );
+        assert_eq!(
+            render("{{relative_url \"//cdn.x.org/a.js\"}}", &data),
+            "//cdn.x.org/a.js"
+        );
+    }
+
+    fn root() -> Value {
+        json!({
+            "source": "",
+            "url": "/",
+            "children": [
+                { "source": "index.md", "url": "/" },
+                { "source": "p.md", "url": "/p/" },
+                {
+                    "source": "seminar",
+                    "url": "/seminar/",
+                    "children": [
+                        { "source": "seminar/index.md", "url": "/seminar/" },
+                        { "source": "seminar/t5.md", "url": "/seminar/t5/" },
+                    ],
+                },
+                {
+                    "source": "archive",
+                    "url": null,
+                    "children": [{ "source": "archive/fall.md", "url": "/archive/fall/" }],
+                },
+            ],
+        })
+    }
+
+    #[test]
+    fn source_urls() {
+        let root = root();
+        assert_eq!(source_url(&root, "p.md").as_deref(), Some("/p/"));
+        assert_eq!(source_url(&root, "/p").as_deref(), Some("/p/"));
+        assert_eq!(
+            source_url(&root, "seminar/t5.md").as_deref(),
+            Some("/seminar/t5/")
+        );
+        // directories and their index pages
+        assert_eq!(source_url(&root, "index.md").as_deref(), Some("/"));
+        assert_eq!(source_url(&root, "seminar").as_deref(), Some("/seminar/"));
+        assert_eq!(
+            source_url(&root, "seminar/index.md").as_deref(),
+            Some("/seminar/")
+        );
+        assert_eq!(source_url(&root, "archive"), None);
+        assert_eq!(source_url(&root, "missing.md"), None);
+    }
+
+    #[test]
+    fn url_for_fails_on_missing_pages() {
+        let mut hb = Handlebars::new();
+        register(&mut hb);
+        let data = json!({ "site": { "root": root() }, "__url__": "/seminar/t5/" });
+        assert_eq!(
+            hb.render_template("{{url_for \"p.md\"}}", &data).unwrap(),
+            "../../p/"
+        );
+        assert!(hb.render_template("{{url_for \"x.md\"}}", &data).is_err());
+    }
+
+    #[test]
+    fn bad_date_formats_fail() {
+        let mut hb = Handlebars::new();
+        register(&mut hb);
+        let data = json!({ "date": "2024-01-02" });
+
+        for format in ["%Q", "%", "%Y-%"] {
+            let template = format!("{{{{format_date date format=\"{}\"}}}}", format);
+            let err = hb.render_template(&template, &data).unwrap_err();
+            assert!(
+                err.to_string().contains(&format!("`{}`", format)),
+                "{}",
+                err
+            );
+        }
+        assert!(hb
+            .render_template("{{format_date date \"%Q\"}}", &data)
+            .is_err());
+        assert!(hb
+            .render_template("{{format_date \"soon\"}}", &data)
+            .is_err());
+    }
+
+    #[test]
+    fn format_date_formats() {
+        let data = json!({ "date": "2024-01-02" });
+        assert_eq!(render("{{format_date date}}", &data), "January 2, 2024");
+        assert_eq!(
+            render("{{format_date date format=\"%Y/%m\"}}", &data),
+            "2024/01"
+        );
+        assert_eq!(render("{{format_date date \"%d.%m.\"}}", &data), "02.01.");
+    }
+}
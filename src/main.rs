@@ -1,24 +1,82 @@
+mod config;
+mod helpers;
+mod serve;
 mod theme;
 mod util;
 mod walker;
+mod watch;
 mod worker;
 
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-use clap::Parser;
-use tracing::info;
+use anyhow::Context;
+use clap::{Parser, Subcommand};
+use tracing::{error, info};
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about=None)]
 struct CLIArguments {
-    #[arg(short, long, default_value = "./src")]
-    source: String,
+    #[command(subcommand)]
+    command: Option<Command>,
 
-    #[arg(short, long, default_value = "./build")]
-    destination: String,
+    // site-wide settings, see config.rs
+    #[arg(short, long, default_value = config::CONFIG_FILE, global = true)]
+    config: String,
 
-    #[arg(short, long, default_value_t = false)]
+    // defaults to the source in the config, or ./src
+    #[arg(short, long, global = true)]
+    source: Option<String>,
+
+    // defaults to the destination in the config, or ./build, or a temporary
+    // directory when serving
+    #[arg(short, long, global = true)]
+    destination: Option<String>,
+
+    #[arg(short, long, default_value_t = false, global = true)]
     force: bool,
+
+    // write into the existing destination instead of replacing it,
+    // files that ferne did not produce (such as .git) are left alone
+    #[arg(short, long, default_value_t = false, global = true)]
+    keep: bool,
+
+    // with --keep, delete the outputs of the last build that no longer have a page
+    #[arg(long, default_value_t = false, global = true)]
+    prune: bool,
+
+    // keep the destination, and only rerender pages whose inputs changed
+    #[arg(short, long, default_value_t = false, global = true)]
+    incremental: bool,
+
+    // also build pages marked with draft = true
+    #[arg(long, default_value_t = false, global = true)]
+    drafts: bool,
+
+    // also build pages whose publish_date is in the future
+    #[arg(long, default_value_t = false, global = true)]
+    future: bool,
+
+    // the url the site is served from, used for the absolute links of feeds.
+    // defaults to localhost when serving
+    #[arg(long, global = true)]
+    base_url: Option<String>,
+
+    // also write a robots.txt that points at the sitemap
+    #[arg(long, default_value_t = false, global = true)]
+    robots: bool,
+}
+
+#[derive(Subcommand, Debug, Clone, Copy, PartialEq, Eq)]
+enum Command {
+    /// Build the site once (the default)
+    Build,
+    /// Build the site, and rebuild it whenever a file in the source changes
+    Watch,
+    /// Watch the site, and serve it on localhost with live reload
+    Serve {
+        #[arg(short, long, default_value_t = 8000)]
+        port: u16,
+    },
 }
 
 #[tokio::main]
@@ -33,13 +91,58 @@ async fn main() -> anyhow::Result<()> {
     }));
 
     let CLIArguments {
+        command,
+        config,
         source,
         destination,
         force,
+        keep,
+        prune,
+        incremental,
+        drafts,
+        future,
+        base_url,
+        robots,
     } = CLIArguments::parse();
 
-    let source = PathBuf::from(source);
-    let destination = PathBuf::from(destination);
+    let command = command.unwrap_or(Command::Build);
+
+    let site = match config::SiteConfig::load(&PathBuf::from(config)).await {
+        Ok(site_) => site_,
+        Err(err) => fatal!("Error: {:#}", err),
+    };
+
+    // the command line takes precedence over the config
+    let source = source
+        .map(PathBuf::from)
+        .or(site.source.clone())
+        .unwrap_or(PathBuf::from("./src"));
+    let destination = destination.map(PathBuf::from).or(site.destination.clone());
+
+    // serving without any destination builds into a directory of our own, which
+    // is removed again when the server shuts down
+    let serve_dir = match (&destination, command) {
+        (None, Command::Serve { .. }) => Some(
+            tempfile::Builder::new()
+                .prefix("ferne-serve-")
+                .tempdir()
+                .context("Failed to create a directory to serve from!")?,
+        ),
+        _ => None,
+    };
+
+    let (destination, force) = match (destination, &serve_dir) {
+        (Some(destination_), _) => (destination_, force),
+        (None, Some(serve_dir_)) => (serve_dir_.path().to_path_buf(), true),
+        (None, None) => (PathBuf::from("./build"), force),
+    };
+
+    // when serving, the urls point at the local server rather than the configured site
+    let base_url = match (base_url, command) {
+        (Some(base_url_), _) => Some(base_url_),
+        (None, Command::Serve { port }) => Some(format!("http://localhost:{}", port)),
+        (None, _) => site.base_url.clone(),
+    };
 
     info!(
         "Building files from `{}` to `{}`.",
@@ -47,18 +150,139 @@ async fn main() -> anyhow::Result<()> {
         destination.display()
     );
 
-    if force {
+    let options = walker::BuildOptions {
+        force,
+        keep,
+        prune,
+        incremental,
+        drafts,
+        future,
+        base_url,
+        robots: robots || site.robots,
+    };
+
+    if force && !options.keeps_destination() {
         info!("Deleting folders while rebuilding due to --force flag set.");
     }
 
+    // The worker outlives every build, so that its cache is shared across rebuilds
     let (resource_worker, queue) = worker::Worker::new(source.clone());
     tokio::spawn(resource_worker.work());
 
-    let template_registry = theme::TemplateRegistry::new(queue.clone())?;
+    let mut live_reload = match command {
+        Command::Serve { .. } => Some(serve::LiveReload::new(destination.clone())),
+        _ => None,
+    };
+
+    let live = live_reload.is_some();
+
+    match build(&source, &destination, options.clone(), &site, live, &queue).await {
+        Ok(route) => {
+            if let Some(live_reload_) = &mut live_reload {
+                live_reload_.update(&route);
+            }
+        }
+        Err(err) => {
+            drop(serve_dir);
+            fatal!("Error: {:#}", err)
+        }
+    }
+
+    if let (Command::Serve { port }, Some(live_reload_)) = (command, &live_reload) {
+        let server = live_reload_.clone().serve(port);
+        tokio::spawn(async move {
+            if let Err(err) = server.await {
+                fatal!("Error: {:?}", err);
+            }
+        });
+    }
+
+    if matches!(command, Command::Watch | Command::Serve { .. }) {
+        let rebuilds = watch_and_rebuild(
+            &source,
+            &destination,
+            options,
+            &site,
+            &mut live_reload,
+            &queue,
+        );
 
-    walker::Walker::new(source, destination, force, template_registry)
-        .walk()
-        .await;
+        tokio::select! {
+            result = rebuilds => result?,
+            _ = tokio::signal::ctrl_c() => info!("Shutting down."),
+        }
+    }
+
+    // removes the directory served from, if it is our own
+    drop(serve_dir);
 
     Ok(())
 }
+
+// Rebuild the site whenever a file in the source changes, until the watcher stops
+async fn watch_and_rebuild(
+    source: &Path,
+    destination: &Path,
+    options: walker::BuildOptions,
+    site: &config::SiteConfig,
+    live_reload: &mut Option<serve::LiveReload>,
+    queue: &worker::SubmitQueue,
+) -> anyhow::Result<()> {
+    let live = live_reload.is_some();
+
+    let mut watcher = watch::SourceWatcher::new(source)?;
+    watcher.ignore(destination);
+    for path in walker::scratch_paths(destination)?.iter() {
+        watcher.ignore(path);
+    }
+
+    info!("Watching `{}` for changes.", source.display());
+
+    while let Some(changed) = watcher.changes().await {
+        info!("Detected {} changed path(s), rebuilding.", changed.len());
+
+        queue.clone().invalidate(changed).await?;
+
+        // the destination was produced by us, so it is always replaced on a rebuild
+        let options = walker::BuildOptions {
+            force: true,
+            ..options.clone()
+        };
+
+        match build(source, destination, options, site, live, queue).await {
+            Ok(route) => {
+                if let Some(live_reload_) = live_reload {
+                    live_reload_.update(&route);
+                }
+            }
+            Err(err) => error!("Error: {:#}", err),
+        }
+    }
+
+    Ok(())
+}
+
+async fn build(
+    source: &Path,
+    destination: &Path,
+    options: walker::BuildOptions,
+    site: &config::SiteConfig,
+    live_reload: bool,
+    queue: &worker::SubmitQueue,
+) -> anyhow::Result<walker::Route> {
+    // Themes are registered afresh on every build, loading them goes through the worker cache
+    let mut template_registry = theme::TemplateRegistry::new(queue.clone(), source.to_path_buf())?;
+    if live_reload {
+        template_registry = template_registry.with_live_reload(&serve::live_reload_script());
+    }
+
+    walker::Walker::new(
+        source.to_path_buf(),
+        destination.to_path_buf(),
+        options,
+        site.clone(),
+        template_registry,
+    )
+    .walk()
+    .await
+}
@@ -0,0 +1,153 @@
+// Serve the built site over HTTP on localhost. Every served page carries a small
+// script that listens for reload notifications, which are sent after a rebuild
+// only for the pages whose output actually changed.
+
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    net::{Ipv4Addr, SocketAddr},
+    path::PathBuf,
+};
+
+use anyhow::{Context, Result};
+use axum::{
+    response::sse::{Event, KeepAlive, Sse},
+    routing::get,
+    Router,
+};
+use tokio::sync::broadcast;
+use tokio_stream::{wrappers::BroadcastStream, Stream, StreamExt};
+use tower_http::services::ServeDir;
+use tracing::info;
+
+use crate::walker::{Route, RouteDetails};
+
+// endpoint streaming the paths of changed pages, one batch per event
+const LIVE_RELOAD_ENDPOINT: &str = "/__ferne/live-reload";
+
+// injected into every page, reloads it if its path is in a batch of changed pages.
+// ENDPOINT stands for LIVE_RELOAD_ENDPOINT, see live_reload_script.
+const LIVE_RELOAD_SCRIPT: &str = r#"<script>
+(() => {
+    const events = new EventSource("ENDPOINT");
+    events.onmessage = (event) => {
+        let path = decodeURIComponent(location.pathname);
+        if (path.endsWith("/")) path += "index.html";
+        if (event.data.split("\n").includes(path)) location.reload();
+    };
+})();
+</script>"#;
+
+pub fn live_reload_script() -> String {
+    LIVE_RELOAD_SCRIPT.replace("ENDPOINT", LIVE_RELOAD_ENDPOINT)
+}
+
+#[derive(Clone, Debug)]
+pub struct LiveReload {
+    destination: PathBuf,
+    pages: HashMap<String, String>, // url path -> html of the last build
+    notify: broadcast::Sender<String>,
+}
+
+#[allow(clippy::needless_arbitrary_self_type)]
+impl LiveReload {
+    pub fn new(destination: PathBuf) -> Self {
+        let (notify, _) = broadcast::channel(16);
+
+        LiveReload {
+            destination,
+            pages: HashMap::new(),
+            notify,
+        }
+    }
+
+    // Record the pages produced by a build, and ask the browsers showing
+    // any page whose html changed since the last build to reload it
+    pub fn update(self: &mut Self, route: &Route) {
+        let mut pages = HashMap::new();
+        collect_pages(route, &mut pages);
+
+        let mut changed = pages
+            .iter()
+            .filter(|(url, html)| self.pages.get(*url) != Some(html))
+            .map(|(url, _)| url.clone())
+            .collect::<Vec<_>>();
+        changed.sort();
+
+        self.pages = pages;
+
+        if !changed.is_empty() {
+            info!("Reloading {} changed page(s).", changed.len());
+            let _ = self.notify.send(changed.join("\n")); // no listeners is fine
+        }
+    }
+
+    pub async fn serve(self: Self, port: u16) -> Result<()> {
+        let notify = self.notify.clone();
+
+        let app = Router::new()
+            .route(
+                LIVE_RELOAD_ENDPOINT,
+                get(move || async move { live_reload_events(notify.subscribe()) }),
+            )
+            .fallback_service(ServeDir::new(&self.destination));
+
+        let address = SocketAddr::from((Ipv4Addr::LOCALHOST, port));
+        let listener = tokio::net::TcpListener::bind(address)
+            .await
+            .context(format!("Failed to bind to `{}`.", address))?;
+
+        info!(
+            "Serving `{}` at http://{}/",
+            self.destination.display(),
+            address
+        );
+
+        axum::serve(listener, app)
+            .await
+            .context("Failed to run the server!")
+    }
+}
+
+fn live_reload_events(
+    changes: broadcast::Receiver<String>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    // a lagging receiver just misses some reloads
+    let stream = BroadcastStream::new(changes)
+        .filter_map(|changed| changed.ok())
+        .map(|changed| Ok(Event::default().data(changed)));
+
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
+
+// Collect the pages in the route tree, keyed by the url path they are served at
+fn collect_pages(route: &Route, pages: &mut HashMap<String, String>) {
+    match &route.details {
+        RouteDetails::Dir(dir) => {
+            for child in dir.children.iter() {
+                collect_pages(child, pages);
+            }
+        }
+        RouteDetails::File(file) => {
+            let url = format!("/{}", file.path.to_string_lossy().replace('\\', "/"));
+            pages.insert(url, file.html.clone());
+
+            for (path, html) in file.later_pages.iter() {
+                let url = format!("/{}", path.to_string_lossy().replace('\\', "/"));
+                pages.insert(url, html.clone());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn script_listens_at_the_endpoint() {
+        let script = live_reload_script();
+        assert!(script.contains(&format!("new EventSource(\"{}\")", LIVE_RELOAD_ENDPOINT)));
+        assert!(!script.contains("ENDPOINT"));
+    }
+}
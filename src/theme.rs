@@ -1,4 +1,8 @@
-use std::sync::Arc;
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
 use anyhow::{Context, Result};
 use handlebars::Handlebars;
@@ -7,7 +11,7 @@ use regex::Regex;
 use tokio::sync::{Mutex, RwLock};
 
 use crate::{
-    qualified_partial,
+    assert_toml_kind, helpers, qualified_partial,
     util::{self, theme_names::sanitize_name},
     walker::{RouteConfig, ThemeConfig},
     worker::SubmitQueue,
@@ -19,28 +23,96 @@ use crate::{
 // sync this with BASE_MAIN_CONTENTS below
 pub const CONTENT_SLOT: &str = "__content__";
 
+// navigation tree of the whole site, and the entry of the page being rendered,
+// available to both the markdown and the theme, see walker::site
+pub const SITE_SLOT: &str = "site";
+pub const PAGE_SLOT: &str = "page";
+
+// the url the page being rendered is served at, which links are made relative to,
+// see helpers
+pub const URL_SLOT: &str = "__url__";
+
+// the pages of a section, available to its index page, see walker::site
+pub const PAGES_SLOT: &str = "pages";
+pub const PAGINATOR_SLOT: &str = "paginator";
+
+// the taxonomy (or term) a taxonomy (or term) page is for, see walker::taxonomy
+pub const TAXONOMY_SLOT: &str = "taxonomy";
+pub const TERM_SLOT: &str = "term";
+
 // The default kind used
 pub const MAIN_KIND: &str = "main";
 
+// The kinds used for generated section listings, and taxonomy pages
+pub const SECTION_KIND: &str = "section";
+pub const TAXONOMY_KIND: &str = "taxonomy";
+pub const TERM_KIND: &str = "term";
+
 // default template name with one kind
 pub const BASE_NAME: &str = "__BASE__";
 
 // template which does nothing except show the raw content
 pub const BASE_MAIN_CONTENTS: &str = r"{{{__content__}}}";
 
+// a theme directory has a <kind>.hbs file per kind, and optionally these
+pub const THEME_MANIFEST_FILE: &str = "theme.toml";
+pub const THEME_ASSETS_DIR: &str = "assets";
+
+// the theme that a theme falls back to for the kinds it does not define
+pub const THEME_EXTENDS_KEY: &str = "extends";
+
 // match lines of type "--- name  : hello_world  " and extract "hello_world"
 const NAME_REGEX_SPEC: &str = r"^---\s*name\s*:\s*([a-zA-Z0-9_]+)\s*$";
 
+// match lines of type "<!-- slot: sidebar -->" and extract "sidebar"
+const SLOT_REGEX_SPEC: &str = r"^\s*<!--\s*slot\s*:\s*([a-zA-Z0-9_]+)\s*-->\s*$";
+
+// a slot cannot take the place of one of the globals
+const RESERVED_SLOTS: [&str; 8] = [
+    CONTENT_SLOT,
+    SITE_SLOT,
+    PAGE_SLOT,
+    URL_SLOT,
+    PAGES_SLOT,
+    PAGINATOR_SLOT,
+    TAXONOMY_SLOT,
+    TERM_SLOT,
+];
+
 #[derive(Clone, Debug)]
 pub struct TemplateRegistry {
     queue: SubmitQueue,
     hb: Arc<RwLock<Handlebars<'static>>>,
     next_tag_idx: Arc<Mutex<u64>>,
+    live_reload: Option<Arc<String>>, // snippet injected into every rendered page
+    themes: Arc<RwLock<HashMap<String, Theme>>>, // theme name -> what it was loaded from
+    source: PathBuf,                  // root of the paths of themes
+}
+
+// The html of the content of a page. A <!-- slot: name --> line in the markdown
+// starts a named slot, which runs until the next such line, and is available to
+// the theme as a variable of that name. Everything before the first one is the
+// main body, in CONTENT_SLOT.
+#[derive(Clone, Debug, Default)]
+pub struct RenderedContent {
+    pub main: String,
+    pub slots: Vec<(String, String)>, // (name, html) in the order they appear
 }
 
+#[derive(Clone, Debug, Default)]
+struct Theme {
+    sources: Vec<String>,    // the resources it was loaded from
+    defaults: toml::Table,   // defaults for the theme config, from theme.toml
+    assets: Option<PathBuf>, // directory copied to the destination
+    parent: Option<String>,  // the theme it extends, see THEME_EXTENDS_KEY
+    dir: Option<PathBuf>,    // the theme directory it was read from, resolved
+}
+
+#[allow(clippy::needless_arbitrary_self_type)]
 impl TemplateRegistry {
-    pub fn new(queue: SubmitQueue) -> Result<Self> {
+    pub fn new(queue: SubmitQueue, source: PathBuf) -> Result<Self> {
         let mut hb = Handlebars::new();
+        helpers::register(&mut hb);
         hb.register_partial(
             &qualified_partial!(BASE_NAME, MAIN_KIND),
             BASE_MAIN_CONTENTS,
@@ -50,29 +122,113 @@ impl TemplateRegistry {
             queue,
             hb: Arc::new(RwLock::new(hb)),
             next_tag_idx: Arc::new(Mutex::new(0)),
+            live_reload: None,
+            themes: Arc::new(RwLock::new(HashMap::new())),
+            source,
         })
     }
 
-    pub async fn has_template(self: &Self, name: &str) -> bool {
-        let hb = self.hb.read().await;
-        return hb.has_template(name);
+    // Inject the given snippet (a <script> tag) into every page rendered by this registry
+    pub fn with_live_reload(mut self: Self, snippet: &str) -> Self {
+        self.live_reload = Some(Arc::new(snippet.to_owned()));
+        self
     }
 
-    // Load a template file, split into parts, and register it.
-    // Each partial starts with a header line that looks like --- name: foobar
-    // Then this loads all the found templates into the registry
-    pub async fn load_template(self: Self, name: Option<String>, path: String) -> Result<String> {
-        static NAME_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(NAME_REGEX_SPEC).unwrap());
+    pub fn has_live_reload(self: &Self) -> bool {
+        self.live_reload.is_some()
+    }
+
+    // The resources a theme (and the themes it extends) was loaded from, along with
+    // their contents
+    pub async fn theme_sources(self: &Self, name: &str) -> Result<Vec<(String, String)>> {
+        let paths = {
+            let themes = self.themes.read().await;
+            chain(&themes, name)
+                .flat_map(|theme| theme.sources.clone())
+                .collect::<Vec<_>>()
+        };
+
+        let mut sources = vec![];
+        for path in paths {
+            let data = self
+                .queue
+                .clone()
+                .submit(path.clone())
+                .await?
+                .get()
+                .await
+                .clone();
+            sources.push((path, data));
+        }
+
+        Ok(sources)
+    }
+
+    // The defaults a theme declares for its config, over those of the themes it extends
+    pub async fn theme_defaults(self: &Self, name: &str) -> Result<toml::Table> {
+        let themes = self.themes.read().await;
+        let ancestry = chain(&themes, name).collect::<Vec<_>>();
+
+        let mut defaults = toml::Table::new();
+        for theme in ancestry.into_iter().rev() {
+            defaults = util::toml::merge(defaults, theme.defaults.clone())?;
+        }
+
+        Ok(defaults)
+    }
+
+    // The asset directories of every theme loaded so far
+    pub async fn theme_assets(self: &Self) -> Vec<PathBuf> {
+        let themes = self.themes.read().await;
+        let mut assets = themes
+            .values()
+            .filter_map(|theme| theme.assets.clone())
+            .collect::<Vec<_>>();
+        assets.sort();
+        assets
+    }
+
+    // Whether the directory holds a theme rather than content: it has a theme.toml,
+    // or a theme has been loaded from it. The walk leaves such directories alone.
+    pub async fn is_theme_dir(self: &Self, path: &Path) -> bool {
+        if tokio::fs::try_exists(path.join(THEME_MANIFEST_FILE))
+            .await
+            .unwrap_or(false)
+        {
+            return true;
+        }
 
+        let Ok(path) = util::paths::resolve(path) else {
+            return false;
+        };
+        let themes = self.themes.read().await;
+        themes
+            .values()
+            .any(|theme| theme.dir.as_ref() == Some(&path))
+    }
+
+    // Load a theme and register its partials. The path is either a single file split
+    // into partials, or a theme directory, see THEME_MANIFEST_FILE. The theme it
+    // extends (if any) has to be loaded already, say by a __common.toml above.
+    pub async fn load_template(
+        self: Self,
+        name: Option<String>,
+        path: String,
+        extends: Option<String>,
+    ) -> Result<String> {
         // Use provided name or produce a new name for the theme
         let name: String = sanitize_name({
             if let Some(name_) = name {
-                if self.has_template(&name_).await {
+                // partials are registered as name:kind, so look for the theme itself,
+                // and hold on to the name while the theme loads
+                let mut themes = self.themes.write().await;
+                if name_ == BASE_NAME || themes.contains_key(&name_) {
                     anyhow::bail!(
                         "Template with name `{}` already present in registry!",
                         name_
                     )
                 }
+                themes.insert(name_.clone(), Theme::default());
                 name_
             } else {
                 let mut idx_lock = self.next_tag_idx.lock().await;
@@ -81,7 +237,62 @@ impl TemplateRegistry {
             }
         })?;
 
-        let data = self.queue.submit(path.clone()).await?.get().await.clone(); // clone the result string
+        let is_dir = tokio::fs::metadata(self.source.join(&path))
+            .await
+            .is_ok_and(|metadata| metadata.is_dir());
+
+        let (partials, theme) = if is_dir {
+            self.read_theme_dir(&path).await?
+        } else {
+            let partials = self.read_split_file(&path).await?;
+            let theme = Theme {
+                sources: vec![path.clone()],
+                ..Theme::default()
+            };
+            (partials, theme)
+        };
+
+        // the one given when loading wins over the one in theme.toml
+        let theme = Theme {
+            parent: extends.or(theme.parent),
+            ..theme
+        };
+
+        if let Some(parent) = &theme.parent {
+            if *parent == name {
+                anyhow::bail!("Theme `{}` extends itself!", path);
+            }
+            if !self.themes.read().await.contains_key(parent) {
+                anyhow::bail!(
+                    "Theme `{}` extends `{}`, which has not been loaded!",
+                    path,
+                    parent
+                );
+            }
+        }
+
+        // Parsing finished, its time to write to the registry
+        let mut hb_write = self.hb.write().await;
+
+        for (kind, buf) in partials {
+            hb_write
+                .register_partial(&qualified_partial!(name, kind), buf)
+                .context(format!("Failed to register template {} to registry!", path))?
+        }
+
+        drop(hb_write);
+
+        self.themes.write().await.insert(name.clone(), theme);
+
+        Ok(name)
+    }
+
+    // Split a template file into its partials, as (kind, contents).
+    // Each partial starts with a header line that looks like --- name: foobar
+    async fn read_split_file(self: &Self, path: &str) -> Result<Vec<(String, String)>> {
+        static NAME_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(NAME_REGEX_SPEC).unwrap());
+
+        let data = self.queue.clone().submit(path).await?.get().await.clone(); // clone the result string
 
         let lines = data.lines().collect::<Vec<_>>();
 
@@ -91,70 +302,357 @@ impl TemplateRegistry {
             let potential_match = NAME_REGEX.captures_at(line, 0);
 
             if let Some(captures) = potential_match {
-                if captures.len() != 1 {
-                    // there should be exactly one match
+                if captures.len() != 2 {
+                    // there should be exactly one match (besides the whole line)
                     anyhow::bail!("Failed to parse template `{}`.", path);
                 }
 
                 // has to succeed since regex has 1 group;
-                let partial = sanitize_name(captures.get(0).unwrap().as_str().to_owned())?;
+                let partial = sanitize_name(captures.get(1).unwrap().as_str().to_owned())?;
                 starts.push((partial, idx))
             }
         }
 
-        // Parsing finished, its time to write to the registry
-        let mut hb_write = self.hb.write().await;
+        let mut partials = vec![];
 
         for idx in 0..starts.len() {
             let id = starts[idx].0.clone();
 
-            let end = if idx == starts.len() {
+            let end = if idx + 1 == starts.len() {
                 lines.len()
             } else {
                 starts[idx + 1].1
             };
 
-            let mut buf = String::new();
-            for line_idx in idx..end {
-                buf.push_str(lines[line_idx]);
-            }
+            // skip the header line itself
+            partials.push((id, lines[starts[idx].1 + 1..end].join("\n")));
+        }
 
-            hb_write
-                .register_partial(&qualified_partial!(name, id), buf)
-                .context(format!("Failed to register template {} to registry!", path))?
+        Ok(partials)
+    }
+
+    // Read a theme directory: a <kind>.hbs file for every kind, an optional
+    // theme.toml with the defaults of the theme config (and the theme it extends),
+    // and an optional assets/ directory that is copied to the destination.
+    async fn read_theme_dir(self: &Self, path: &str) -> Result<(Vec<(String, String)>, Theme)> {
+        let dir = self.source.join(path);
+        let resource = |file_name: &str| format!("{}/{}", path.trim_end_matches('/'), file_name);
+
+        let mut entries = tokio::fs::read_dir(&dir).await.context(format!(
+            "Could not read theme directory `{}`",
+            dir.display()
+        ))?;
+
+        let mut partials = vec![];
+        let mut theme = Theme {
+            dir: Some(util::paths::resolve(&dir)?),
+            ..Theme::default()
+        };
+
+        while let Some(entry) = entries.next_entry().await.context(format!(
+            "Failed to read theme directory `{}`",
+            dir.display()
+        ))? {
+            let entry_path = entry.path();
+            let file_name = entry.file_name().to_string_lossy().into_owned();
+
+            if file_name == THEME_ASSETS_DIR && entry_path.is_dir() {
+                theme.assets = Some(entry_path);
+            } else if file_name == THEME_MANIFEST_FILE {
+                let data = self.queue.clone().submit(resource(&file_name)).await?;
+                let mut defaults: toml::Table = toml::from_str(data.get().await).context(
+                    format!("Failed to parse toml in file `{}`.", entry_path.display()),
+                )?;
+
+                theme.parent = assert_toml_kind!(String; defaults, THEME_EXTENDS_KEY)?;
+                defaults.remove(THEME_EXTENDS_KEY);
+
+                theme.defaults = defaults;
+                theme.sources.push(resource(&file_name));
+            } else if util::paths::ext_is(&entry_path, "hbs") {
+                let kind = sanitize_name(
+                    entry_path
+                        .file_stem()
+                        .unwrap_or_default()
+                        .to_string_lossy()
+                        .into_owned(),
+                )?;
+                let data = self.queue.clone().submit(resource(&file_name)).await?;
+                partials.push((kind, data.get().await.clone()));
+                theme.sources.push(resource(&file_name));
+            }
         }
 
-        Ok(name) // write lock dropped here
+        // the order of the entries is up to the file system
+        theme.sources.sort();
+
+        Ok((partials, theme))
+    }
+
+    // The partial rendering the kind for a theme: its own if it defines one, or else
+    // the first found up the themes it extends. Rendering reports a kind that is
+    // defined nowhere.
+    async fn resolve_partial(self: &Self, name: &str, kind: &str) -> String {
+        let themes = self.themes.read().await;
+        let hb = self.hb.read().await;
+
+        let mut names = vec![name.to_owned()];
+        names.extend(chain(&themes, name).filter_map(|theme| theme.parent.clone()));
+
+        names
+            .iter()
+            .map(|name_| qualified_partial!(name_, kind))
+            .find(|partial| hb.has_template(partial))
+            .unwrap_or_else(|| qualified_partial!(name, kind))
     }
 
+    // Render the markdown content with the route config (other than theme) into html,
+    // see RenderedContent. The globals are available to the markdown too.
+    pub async fn render_content(
+        self: &Self,
+        content: &str,
+        config: &RouteConfig,
+        globals: &serde_json::Map<String, serde_json::Value>,
+    ) -> Result<RenderedContent> {
+        let mut config_with_globals = util::toml::table_to_json(&config.rest);
+        config_with_globals.extend(globals.clone());
+
+        let markdown = self
+            .hb
+            .read()
+            .await
+            .render_template(content, &config_with_globals)?;
+
+        // split into slots, and convert the markdown of each to html
+        let (main, slots) = split_slots(&markdown)?;
+
+        Ok(RenderedContent {
+            main: util::markdown::to_html(&main),
+            slots: slots
+                .into_iter()
+                .map(|(name, markdown_)| (name, util::markdown::to_html(&markdown_)))
+                .collect(),
+        })
+    }
+
+    // Render the theme with the html of the content, see render_content
     pub async fn render_template(
         self: Self,
-        content: &str,
+        content: RenderedContent,
         config: &RouteConfig,
+        globals: &serde_json::Map<String, serde_json::Value>,
     ) -> Result<String> {
-        let TemplateRegistry { hb, .. } = self;
         let ThemeConfig {
             ref name,
             ref kind,
             rest: ref theme_rest,
         } = config.theme;
 
-        let hb = hb.read().await;
-
-        // render the markdown using the configuration (other than theme)
-        let markdown = hb.render_template(content, &config.rest)?;
+        let partial = self.resolve_partial(name, kind).await;
+        let TemplateRegistry {
+            hb, live_reload, ..
+        } = self;
 
-        // convert markdown to html
-        let md_as_html = util::markdown::to_html(&markdown);
+        let hb = hb.read().await;
 
-        // then render the theme with the rendered markdown as content
         // first copy the theme config, and insert the content
         // use that as the data to render the template
-        let mut config_with_content = theme_rest.clone();
-        config_with_content.insert(CONTENT_SLOT.to_owned(), toml::Value::String(md_as_html));
+        let mut config_with_content = util::toml::table_to_json(theme_rest);
+        config_with_content.extend(globals.clone());
+        config_with_content.insert(
+            CONTENT_SLOT.to_owned(),
+            serde_json::Value::String(content.main),
+        );
+        for (slot, html) in content.slots {
+            config_with_content.insert(slot, serde_json::Value::String(html));
+        }
+
+        let mut rendered = hb.render(&partial, &config_with_content)?;
 
-        let rendered = hb.render(&qualified_partial!(name, kind), &config_with_content)?;
+        if let Some(snippet) = live_reload {
+            inject_before_body_end(&mut rendered, &snippet);
+        }
 
         Ok(rendered) // read lock dropped here
     }
 }
+
+// Split markdown at the <!-- slot: name --> lines into the main body, and the
+// named slots, see RenderedContent. Such lines inside fenced code are just code.
+fn split_slots(markdown: &str) -> Result<(String, Vec<(String, String)>)> {
+    static SLOT_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(SLOT_REGEX_SPEC).unwrap());
+
+    let mut main = vec![];
+    let mut slots: Vec<(String, Vec<&str>)> = vec![];
+    let mut open_fence = None; // (fence character, length) of the code block we are in
+
+    for line in markdown.lines() {
+        let fence = code_fence(line);
+        let captures = SLOT_REGEX.captures(line).filter(|_| open_fence.is_none());
+
+        match (open_fence, fence) {
+            (None, Some((character, length, _))) => open_fence = Some((character, length)),
+            (Some((character, length)), Some((character_, length_, "")))
+                if character == character_ && length <= length_ =>
+            {
+                open_fence = None
+            }
+            _ => {}
+        }
+
+        let Some(captures) = captures else {
+            match slots.last_mut() {
+                Some((_, lines)) => lines.push(line),
+                None => main.push(line),
+            }
+            continue;
+        };
+
+        let slot = captures[1].to_owned();
+        if RESERVED_SLOTS.contains(&slot.as_str()) {
+            anyhow::bail!("Slot `{}` would hide the variable of that name!", slot);
+        }
+        if slots.iter().any(|(name, _)| *name == slot) {
+            anyhow::bail!("Slot `{}` is filled more than once!", slot);
+        }
+        slots.push((slot, vec![]));
+    }
+
+    let slots = slots
+        .into_iter()
+        .map(|(name, lines)| (name, lines.join("\n")))
+        .collect();
+
+    Ok((main.join("\n"), slots))
+}
+
+// The fence character, the length of the fence, and the info string after it, if
+// the line is a code fence: at least three backticks or tildes, indented by at
+// most three spaces
+fn code_fence(line: &str) -> Option<(char, usize, &str)> {
+    let indent = line.len() - line.trim_start_matches(' ').len();
+    let line = line.trim_start_matches(' ');
+    let character = line.chars().next().filter(|c| *c == '`' || *c == '~')?;
+    let length = line.len() - line.trim_start_matches(character).len();
+
+    if indent > 3 || length < 3 {
+        return None;
+    }
+
+    Some((character, length, line[length..].trim()))
+}
+
+// The theme of this name followed by the themes it extends, nearest first. A theme
+// can only extend one loaded before it, but the chain stops at a theme seen
+// before all the same.
+fn chain<'a>(themes: &'a HashMap<String, Theme>, name: &str) -> impl Iterator<Item = &'a Theme> {
+    let mut visited = HashSet::new();
+    let mut next = themes.get_key_value(name);
+    std::iter::from_fn(move || {
+        let (name_, theme) = next?;
+        if !visited.insert(name_) {
+            return None;
+        }
+        next = theme
+            .parent
+            .as_ref()
+            .and_then(|parent| themes.get_key_value(parent));
+        Some(theme)
+    })
+}
+
+// Insert the snippet right before the closing </body> tag, or at the end
+// if the page has none
+fn inject_before_body_end(html: &mut String, snippet: &str) {
+    if let Some(idx) = html.rfind("</body>") {
+        html.insert_str(idx, snippet);
+    } else {
+        html.push_str(snippet);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn theme(parent: Option<&str>) -> Theme {
+        Theme {
+            parent: parent.map(str::to_owned),
+            ..Theme::default()
+        }
+    }
+
+    #[test]
+    fn slots_split_at_markers() {
+        let markdown = "# Talk\nbody\n<!-- slot: sidebar -->\n- a\n  <!--slot:abstract-->  \nshort";
+        let (main, slots) = split_slots(markdown).unwrap();
+        assert_eq!(main, "# Talk\nbody");
+        assert_eq!(
+            slots,
+            vec![
+                ("sidebar".to_owned(), "- a".to_owned()),
+                ("abstract".to_owned(), "short".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn slots_cannot_repeat_or_hide_globals() {
+        assert!(split_slots("<!-- slot: a -->\nx\n<!-- slot: a -->").is_err());
+        for name in RESERVED_SLOTS {
+            let markdown = format!("x\n<!-- slot: {} -->\ny", name);
+            assert!(
+                split_slots(&markdown).is_err(),
+                "slot `{}` was accepted",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn slots_are_not_split_in_fenced_code() {
+        let markdown =
+            "````md\n<!-- slot: sidebar -->\n```\nstill code\n````\n<!-- slot: notes -->\nn";
+        let (main, slots) = split_slots(markdown).unwrap();
+        assert_eq!(
+            main,
+            "````md\n<!-- slot: sidebar -->\n```\nstill code\n````"
+        );
+        assert_eq!(slots, vec![("notes".to_owned(), "n".to_owned())]);
+
+        let (main, slots) = split_slots("~~~\n<!-- slot: a -->\n~~~ not closed\n").unwrap();
+        assert!(slots.is_empty());
+        assert!(main.contains("<!-- slot: a -->"));
+    }
+
+    #[test]
+    fn code_fences() {
+        assert_eq!(code_fence("```rust"), Some(('`', 3, "rust")));
+        assert_eq!(code_fence("   ~~~~"), Some(('~', 4, "")));
+        assert_eq!(code_fence("    ```"), None); // indented code instead
+        assert_eq!(code_fence("``"), None);
+    }
+
+    #[test]
+    fn chain_follows_parents() {
+        let themes = HashMap::from([
+            ("talk".to_owned(), theme(Some("dept"))),
+            ("dept".to_owned(), theme(Some("base"))),
+            ("base".to_owned(), theme(None)),
+        ]);
+        assert_eq!(chain(&themes, "talk").count(), 3);
+        assert_eq!(chain(&themes, "base").count(), 1);
+        assert_eq!(chain(&themes, "missing").count(), 0);
+    }
+
+    #[test]
+    fn chain_stops_at_cycles() {
+        let themes = HashMap::from([
+            ("a".to_owned(), theme(Some("b"))),
+            ("b".to_owned(), theme(Some("a"))),
+            ("c".to_owned(), theme(Some("c"))),
+        ]);
+        assert_eq!(chain(&themes, "a").count(), 2);
+        assert_eq!(chain(&themes, "c").count(), 1);
+    }
+}
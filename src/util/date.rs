@@ -0,0 +1,92 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, FixedOffset, Local, NaiveDate, NaiveDateTime, TimeZone};
+
+// formats accepted for dates without an offset, which are taken in local time
+const NAIVE_DATETIME_FORMATS: [&str; 4] = [
+    "%Y-%m-%dT%H:%M:%S%.f",
+    "%Y-%m-%d %H:%M:%S%.f",
+    "%Y-%m-%dT%H:%M",
+    "%Y-%m-%d %H:%M",
+];
+const NAIVE_DATE_FORMAT: &str = "%Y-%m-%d";
+
+// Read a date from a toml value, which is either a toml datetime or a string
+pub fn from_toml(value: &toml::Value) -> Result<DateTime<FixedOffset>> {
+    match value {
+        toml::Value::Datetime(datetime) => parse(&datetime.to_string()),
+        toml::Value::String(string) => parse(string),
+        _ => anyhow::bail!("Expected a date, found `{}`.", value),
+    }
+}
+
+pub fn parse(text: &str) -> Result<DateTime<FixedOffset>> {
+    let text = text.trim();
+
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(text) {
+        return Ok(datetime);
+    }
+
+    let naive = NAIVE_DATETIME_FORMATS
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(text, format).ok())
+        .or_else(|| {
+            NaiveDate::parse_from_str(text, NAIVE_DATE_FORMAT)
+                .ok()
+                .and_then(|date| date.and_hms_opt(0, 0, 0))
+        })
+        .context(format!("Failed to parse date `{}`.", text))?;
+
+    let local = Local
+        .from_local_datetime(&naive)
+        .earliest()
+        .context(format!("Date `{}` does not exist in local time.", text))?;
+
+    Ok(local.fixed_offset())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dates_with_offsets() {
+        let date = parse("2024-09-12T14:30:00+02:00").unwrap();
+        assert_eq!(date.offset().local_minus_utc(), 2 * 3600);
+        assert_eq!(date.to_rfc3339(), "2024-09-12T14:30:00+02:00");
+    }
+
+    #[test]
+    fn dates_in_local_time() {
+        let expected = |text: &str| {
+            let naive = NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M").unwrap();
+            Local.from_local_datetime(&naive).earliest().unwrap()
+        };
+
+        assert_eq!(parse(" 2024-09-12 ").unwrap(), expected("2024-09-12 00:00"));
+        assert_eq!(
+            parse("2024-09-12 14:30").unwrap(),
+            expected("2024-09-12 14:30")
+        );
+        assert_eq!(
+            parse("2024-09-12T14:30:00").unwrap(),
+            expected("2024-09-12 14:30")
+        );
+    }
+
+    #[test]
+    fn bad_dates() {
+        assert!(parse("").is_err());
+        assert!(parse("12/09/2024").is_err());
+        assert!(parse("2024-02-30").is_err());
+    }
+
+    #[test]
+    fn toml_dates() {
+        let table: toml::Table = toml::from_str("a = 2024-09-12T14:30:00Z\nb = 3").unwrap();
+        assert_eq!(
+            from_toml(&table["a"]).unwrap().to_rfc3339(),
+            "2024-09-12T14:30:00+00:00"
+        );
+        assert!(from_toml(&table["b"]).is_err());
+    }
+}
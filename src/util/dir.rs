@@ -1,29 +1,102 @@
 use std::path::PathBuf;
 
+use anyhow::{Context, Result};
+use async_recursion::async_recursion;
+
 use crate::{fatal, fatal_if_err};
 
-// Remove the already existing directory, if necessary (and force),
-// and then create a new directory in its place
-pub async fn remove_and_create(path: &PathBuf, force: bool) {
+// Check that an existing directory may be replaced (needs force)
+pub async fn ensure_replaceable(path: &PathBuf, force: bool) {
     let path_disp = path.display();
 
     let exists = fatal_if_err!(tokio::fs::try_exists(path).await;
         "Error reading path `{}`.", path_disp);
 
+    if exists && !force {
+        fatal!(
+            "Directory `{}` already exists. Use --force to force deletion.",
+            path_disp
+        );
+    }
+}
+
+// Create the directory (and its parents) unless it already exists
+pub async fn create(path: &PathBuf) {
+    fatal_if_err!(tokio::fs::create_dir_all(path).await;
+        "Failed to create directory `{}`!", path.display());
+}
+
+// Remove the directory and everything in it, if it exists
+pub async fn remove_if_exists(path: &PathBuf) {
+    let exists = fatal_if_err!(tokio::fs::try_exists(path).await;
+        "Error reading path `{}`.", path.display());
+
     if exists {
-        if !force {
-            fatal!(
-                "Directory `{}` already exists. Use --force to force deletion.",
-                path_disp
-            );
-        } else {
-            fatal_if_err!(tokio::fs::remove_dir_all(path).await;
-                "Failed to delete directory `{}`!", path_disp);
+        fatal_if_err!(tokio::fs::remove_dir_all(path).await;
+            "Failed to delete directory `{}`!", path.display());
+    }
+}
+
+// Mirror the directory tree at source into destination, hard linking the files
+// where possible and copying them otherwise. Files in the mirror must be replaced,
+// not written in place, since they may share their contents with the source.
+#[async_recursion]
+pub async fn link_tree(source: &PathBuf, destination: &PathBuf) -> Result<()> {
+    tokio::fs::create_dir_all(destination)
+        .await
+        .context(format!(
+            "Failed to create directory `{}`!",
+            destination.display()
+        ))?;
+
+    let mut entries = tokio::fs::read_dir(source)
+        .await
+        .context(format!("Could not read directory `{}`", source.display()))?;
+
+    while let Some(entry) = entries
+        .next_entry()
+        .await
+        .context(format!("Failed to read directory `{}`", source.display()))?
+    {
+        let from = entry.path();
+        let to = destination.join(entry.file_name());
+
+        if entry.file_type().await?.is_dir() {
+            link_tree(&from, &to).await?;
+        } else if tokio::fs::hard_link(&from, &to).await.is_err() {
+            tokio::fs::copy(&from, &to)
+                .await
+                .context(format!("Failed to copy `{}`.", from.display()))?;
         }
     }
 
-    // Directory does not exist at this point
+    Ok(())
+}
+
+// Move the staging directory into the place of destination, keeping the window in which
+// neither the old nor the new directory is at destination down to two renames
+pub async fn swap_in(staging: &PathBuf, destination: &PathBuf, old: &PathBuf) -> Result<()> {
+    let exists = tokio::fs::try_exists(destination)
+        .await
+        .context(format!("Error reading path `{}`.", destination.display()))?;
+
+    if exists {
+        remove_if_exists(old).await;
+        tokio::fs::rename(destination, old)
+            .await
+            .context(format!("Failed to move `{}` aside.", destination.display()))?;
+    }
+
+    tokio::fs::rename(staging, destination)
+        .await
+        .context(format!(
+            "Failed to move `{}` into place.",
+            staging.display()
+        ))?;
+
+    if exists {
+        remove_if_exists(old).await;
+    }
 
-    fatal_if_err!(tokio::fs::create_dir(path).await;
-        "Failed to create directory `{}`!", path_disp);
+    Ok(())
 }
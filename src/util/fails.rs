@@ -17,7 +17,7 @@ macro_rules! fatal_if_err {
         if let Ok(result_) = result {
             result_
         } else {
-            crate::fatal!($msg)
+            $crate::fatal!($msg)
         }
     }};
     ($eval:expr; $msg:expr $(,$args:expr)*) => {{
@@ -25,7 +25,7 @@ macro_rules! fatal_if_err {
         if let Ok(result_) = result {
             result_
         } else {
-            crate::fatal!($msg $(,$args)*)
+            $crate::fatal!($msg $(,$args)*)
         }
     }}
 }
@@ -0,0 +1,14 @@
+// FNV-1a, used to fingerprint file contents. Unlike std's DefaultHasher, the
+// output is stable across compiler versions, so it can be persisted between builds.
+
+const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+pub fn fingerprint(data: &[u8]) -> String {
+    let mut hash = FNV_OFFSET;
+    for byte in data {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(FNV_PRIME);
+    }
+    format!("{:016x}", hash)
+}
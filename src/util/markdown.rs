@@ -1,3 +1,219 @@
+use anyhow::{Context, Result};
+
+// fences of a front matter block at the very top of a markdown file
+const TOML_FENCE: &str = "+++";
+const YAML_FENCE: &str = "---";
+
 pub fn to_html(md: &str) -> String {
     markdown::to_html(md)
 }
+
+// Like to_html, but without the <p> around text that makes up a single paragraph,
+// for short strings placed inside other elements
+pub fn to_inline_html(md: &str) -> String {
+    let html = to_html(md);
+    let inner = html
+        .trim_end()
+        .strip_prefix("<p>")
+        .and_then(|rest| rest.strip_suffix("</p>"));
+
+    match inner {
+        Some(inner_) if !inner_.contains("<p>") => inner_.to_owned(),
+        _ => html,
+    }
+}
+
+// Split a TOML (fenced with +++) or YAML (fenced with ---) front matter block off the
+// top of a markdown file. Returns the parsed front matter (empty if there is none),
+// and the rest of the file.
+pub fn split_front_matter(md: &str) -> Result<(toml::Table, &str)> {
+    let first_line = md.lines().next().unwrap_or("").trim_end();
+
+    if first_line != TOML_FENCE && first_line != YAML_FENCE {
+        return Ok((toml::Table::new(), md));
+    }
+
+    // find the closing fence, which has to be on a line of its own
+    let body_start = md.find('\n').map_or(md.len(), |idx| idx + 1);
+
+    // a --- followed by a blank line is a thematic break (as in pandoc), since yaml
+    // front matter starts right below its fence
+    let second_line = md[body_start..].lines().next();
+    if first_line == YAML_FENCE && second_line.is_some_and(|line| line.trim().is_empty()) {
+        return Ok((toml::Table::new(), md));
+    }
+    let mut offset = body_start;
+    let mut closing = None;
+
+    for line in md[body_start..].split_inclusive('\n') {
+        if line.trim_end() == first_line {
+            closing = Some((offset, offset + line.len()));
+            break;
+        }
+        offset += line.len();
+    }
+
+    let Some((block_end, rest_start)) = closing else {
+        // no closing fence, so this is not front matter
+        return Ok((toml::Table::new(), md));
+    };
+
+    let block = &md[body_start..block_end];
+
+    let table = if first_line == TOML_FENCE {
+        toml::from_str::<toml::Table>(block).context("Failed to parse toml front matter.")?
+    } else if block.trim().is_empty() {
+        toml::Table::new()
+    } else {
+        let yaml = serde_yaml_ng::from_str::<serde_yaml_ng::Mapping>(block)
+            .context("Failed to parse yaml front matter.")?;
+        yaml_table(yaml, "").context("Failed to read yaml front matter.")?
+    };
+
+    Ok((table, &md[rest_start..]))
+}
+
+// YAML front matter ends up as the same table as TOML front matter would:
+// - a null (`~`, or a key without a value) leaves the key out, since toml has no
+//   null, and a null inside an array is dropped
+// - arrays may mix types, as they can in toml
+// - keys that are numbers or booleans become strings
+// - dates are plain strings in yaml, every date key accepts them as such
+// - a tagged value (`!tag value`) is read as its value
+// Anything else, such as a key that is itself a list or an integer too large for
+// toml, is an error naming the key.
+fn yaml_table(yaml: serde_yaml_ng::Mapping, path: &str) -> Result<toml::Table> {
+    let mut table = toml::Table::new();
+
+    for (key, value) in yaml {
+        let key = match key {
+            serde_yaml_ng::Value::String(key_) => key_,
+            serde_yaml_ng::Value::Number(number) => number.to_string(),
+            serde_yaml_ng::Value::Bool(boolean) => boolean.to_string(),
+            _ => anyhow::bail!("Key `{:?}` in `{}` is not a string.", key, path),
+        };
+        let key_path = if path.is_empty() {
+            key.clone()
+        } else {
+            format!("{}.{}", path, key)
+        };
+
+        if let Some(value_) = yaml_value(value, &key_path)? {
+            table.insert(key, value_);
+        }
+    }
+
+    Ok(table)
+}
+
+fn yaml_value(yaml: serde_yaml_ng::Value, path: &str) -> Result<Option<toml::Value>> {
+    let value = match yaml {
+        serde_yaml_ng::Value::Null => return Ok(None),
+        serde_yaml_ng::Value::Bool(boolean) => toml::Value::Boolean(boolean),
+        serde_yaml_ng::Value::Number(number) => match (number.as_i64(), number.as_f64()) {
+            (Some(integer), _) => toml::Value::Integer(integer),
+            (None, Some(float)) if !number.is_u64() => toml::Value::Float(float),
+            _ => anyhow::bail!("Number `{}` of `{}` is too large.", number, path),
+        },
+        serde_yaml_ng::Value::String(string) => toml::Value::String(string),
+        serde_yaml_ng::Value::Sequence(items) => {
+            let mut array = vec![];
+            for item in items {
+                array.extend(yaml_value(item, path)?);
+            }
+            toml::Value::Array(array)
+        }
+        serde_yaml_ng::Value::Mapping(mapping) => toml::Value::Table(yaml_table(mapping, path)?),
+        serde_yaml_ng::Value::Tagged(tagged) => return yaml_value(tagged.value, path),
+    };
+
+    Ok(Some(value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn toml_and_yaml_front_matter() {
+        let (table, rest) = split_front_matter("+++\ntitle = \"A\"\n+++\n# A\n").unwrap();
+        assert_eq!(table["title"].as_str(), Some("A"));
+        assert_eq!(rest, "# A\n");
+
+        let (table, rest) = split_front_matter("---\ntitle: B\n---\nbody").unwrap();
+        assert_eq!(table["title"].as_str(), Some("B"));
+        assert_eq!(rest, "body");
+
+        let (table, rest) = split_front_matter("+++\n+++").unwrap();
+        assert!(table.is_empty());
+        assert_eq!(rest, "");
+    }
+
+    #[test]
+    fn no_front_matter() {
+        let md = "# Title\n+++\nnot = \"front matter\"\n+++\n";
+        let (table, rest) = split_front_matter(md).unwrap();
+        assert!(table.is_empty());
+        assert_eq!(rest, md);
+
+        assert!(split_front_matter("").unwrap().0.is_empty());
+    }
+
+    #[test]
+    fn yaml_becomes_toml() {
+        let md = "---\ntitle: A\nsubtitle: ~\nempty:\nmixed: [1, b, 2.5, null, true]\n\
+                  date: 2024-09-12\n2024: year\nspeaker: { name: C, room: !room B2 }\n---\n";
+        let (table, _) = split_front_matter(md).unwrap();
+
+        assert_eq!(table["title"].as_str(), Some("A"));
+        assert!(!table.contains_key("subtitle"));
+        assert!(!table.contains_key("empty"));
+        assert_eq!(
+            table["mixed"],
+            toml::Value::Array(vec![
+                toml::Value::Integer(1),
+                toml::Value::String("b".to_owned()),
+                toml::Value::Float(2.5),
+                toml::Value::Boolean(true),
+            ])
+        );
+        assert_eq!(table["date"].as_str(), Some("2024-09-12"));
+        assert_eq!(table["2024"].as_str(), Some("year"));
+        assert_eq!(table["speaker"]["room"].as_str(), Some("B2"));
+
+        let err = split_front_matter("---\nbig: 18446744073709551615\n---\n").unwrap_err();
+        assert!(format!("{:#}", err).contains("`big`"));
+        assert!(split_front_matter("---\n? [a, b]\n: c\n---\n").is_err());
+    }
+
+    #[test]
+    fn unclosed_fence_is_not_front_matter() {
+        let md = "+++\ntitle = \"A\"\n++++\nbody";
+        let (table, rest) = split_front_matter(md).unwrap();
+        assert!(table.is_empty());
+        assert_eq!(rest, md);
+    }
+
+    #[test]
+    fn thematic_breaks_are_not_front_matter() {
+        let md = "---\n\nIntro, then a break.\n\n---\n\nMore";
+        let (table, rest) = split_front_matter(md).unwrap();
+        assert!(table.is_empty());
+        assert_eq!(rest, md);
+
+        let md = "Intro\n\n---\n\nMore\n---\n";
+        assert_eq!(split_front_matter(md).unwrap().1, md);
+    }
+
+    #[test]
+    fn broken_front_matter_fails() {
+        assert!(split_front_matter("+++\ntitle = \n+++\n").is_err());
+        assert!(split_front_matter("---\n- a list\n---\n").is_err());
+    }
+
+    #[test]
+    fn inline_html_drops_the_paragraph() {
+        assert_eq!(to_inline_html("*a* b"), "<em>a</em> b");
+        assert!(to_inline_html("a\n\nb").starts_with("<p>"));
+    }
+}
@@ -1,6 +1,10 @@
+pub mod date;
 pub mod dir;
 pub mod fails;
+pub mod hash;
 pub mod markdown;
 pub mod paths;
+pub mod slug;
 pub mod theme_names;
 pub mod toml;
+pub mod xml;
@@ -1,4 +1,4 @@
-use std::path::PathBuf;
+use std::path::{Component, Path, PathBuf};
 
 use anyhow::Context;
 
@@ -30,15 +30,59 @@ pub async fn read(path: &PathBuf) -> anyhow::Result<String> {
         .context(format!("Failed to read file `{}`!", path.display()))
 }
 
+// write a file with tokio, unless it already has exactly these contents,
+// so that unchanged files keep their modification times
+pub async fn write_if_changed(path: &PathBuf, contents: &str) -> anyhow::Result<()> {
+    if let Ok(existing) = tokio::fs::read(path).await {
+        if existing == contents.as_bytes() {
+            return Ok(());
+        }
+
+        // replace the file instead of writing in place, it may be hard linked elsewhere
+        tokio::fs::remove_file(path)
+            .await
+            .context(format!("Failed to delete file `{}`.", path.display()))?;
+    }
+
+    tokio::fs::write(path, contents)
+        .await
+        .context(format!("Failed to write to path `{}`.", path.display()))
+}
+
+// A relative path as a string, with forward slashes on every platform
+pub fn path_name(path: &Path) -> String {
+    path.to_string_lossy().replace('\\', "/")
+}
+
 // check if extension matches
-pub fn ext_is(val: &PathBuf, ext: &str) -> bool {
+pub fn ext_is(val: &Path, ext: &str) -> bool {
     if let Some(val_) = val.extension() {
-        if val_.to_string_lossy().eq(ext) {
-            true
-        } else {
-            false
-        }
+        val_.to_string_lossy().eq(ext)
     } else {
         false
     }
 }
+
+// The absolute form of a path, with `.`, `..` and symlinks resolved. The part of the
+// path that does not exist yet is resolved by name only.
+pub fn resolve(path: &Path) -> anyhow::Result<PathBuf> {
+    let absolute = std::path::absolute(path)
+        .context(format!("Failed to resolve path `{}`.", path.display()))?;
+
+    let mut resolved = PathBuf::new();
+    for component in absolute.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                resolved.pop();
+            }
+            _ => resolved.push(component),
+        }
+        // so that a .. after a symlink leaves where the link points
+        if let Ok(canonical) = resolved.canonicalize() {
+            resolved = canonical;
+        }
+    }
+
+    Ok(resolved)
+}
@@ -0,0 +1,29 @@
+// Turn a name into something usable in a url: lowercase letters and digits,
+// with every other run of characters replaced by a single dash.
+pub fn slugify(name: &str) -> String {
+    let mut slug = String::new();
+
+    for c in name.chars() {
+        if c.is_alphanumeric() {
+            slug.extend(c.to_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+
+    slug.trim_end_matches('-').to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slugs() {
+        assert_eq!(slugify("Spectral Theory"), "spectral-theory");
+        assert_eq!(slugify("  C++ & Rust!  "), "c-rust");
+        assert_eq!(slugify("Ünïcode Straße"), "ünïcode-straße");
+        assert_eq!(slugify("2024/09/12"), "2024-09-12");
+        assert_eq!(slugify("--"), "");
+    }
+}
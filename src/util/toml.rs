@@ -47,7 +47,7 @@ pub fn merge(mut base: toml::Table, overlay: toml::Table) -> Result<toml::Table>
                         *previous_value = value;
                     } else {
                         if let Array(mut overlay_array) = value {
-                            array.extend(overlay_array.drain(..));
+                            array.append(&mut overlay_array);
                         } else {
                             anyhow::bail!("Type error during array merge!");
                         }
@@ -74,14 +74,41 @@ pub fn merge(mut base: toml::Table, overlay: toml::Table) -> Result<toml::Table>
     Ok(base)
 }
 
-pub async fn read(path: &PathBuf) -> Result<toml::Table> {
+// Returns the parsed table, along with the raw contents of the file (empty if missing)
+pub async fn read(path: &PathBuf) -> Result<(toml::Table, String)> {
     // Fails only if toml is provided but fails to parse. Missing file just returns an
     // empty table.
 
     let contents = paths::read(path).await.unwrap_or("".to_owned());
 
-    toml::from_str::<toml::Table>(&contents).context(format!(
+    let table = toml::from_str::<toml::Table>(&contents).context(format!(
         "Failed to parse toml in file `{}`.",
         path.display()
-    ))
+    ))?;
+
+    Ok((table, contents))
+}
+
+// Convert a toml value to json, with datetimes as their toml string representation
+pub fn to_json(value: &toml::Value) -> serde_json::Value {
+    use toml::Value::*;
+
+    match value {
+        String(string) => serde_json::Value::String(string.clone()),
+        Integer(integer) => serde_json::Value::from(*integer),
+        Float(float) => serde_json::Value::from(*float),
+        Boolean(boolean) => serde_json::Value::Bool(*boolean),
+        Datetime(datetime) => serde_json::Value::String(datetime.to_string()),
+        Array(array) => serde_json::Value::Array(array.iter().map(to_json).collect()),
+        Table(table) => serde_json::Value::Object(table_to_json(table)),
+    }
+}
+
+pub fn table_to_json(
+    table: &toml::Table,
+) -> serde_json::Map<std::string::String, serde_json::Value> {
+    table
+        .iter()
+        .map(|(key, value)| (key.clone(), to_json(value)))
+        .collect()
 }
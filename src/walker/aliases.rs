@@ -0,0 +1,152 @@
+// Redirects for moved pages. A page can list the urls it used to be at with
+// `aliases = ["/old/path.html", "/older/path/"]`, and every alias gets a small page
+// that redirects to the current url. Aliases are read from the config of the page
+// itself, since they would point at every page below a __common.toml otherwise.
+//
+// For hosts that read one, `[output] redirects = true` in ferne.toml also writes a
+// _redirects file with all the aliases of the site.
+
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+use super::{
+    permalink,
+    route::{Route, RouteDetails},
+    site,
+};
+use crate::{assert_toml_kind, util, util::xml::escape};
+
+pub const REDIRECTS_FILE: &str = "_redirects";
+const ALIASES_KEY: &str = "aliases";
+
+// The aliases a page declares, as urls with a leading /. An alias listed twice
+// counts once, and one outside of the site is an error.
+pub fn from_toml(own: &toml::Table) -> Result<Vec<String>> {
+    let declared = assert_toml_kind!(Array; own, ALIASES_KEY)?.unwrap_or_default();
+
+    let mut aliases: Vec<String> = vec![];
+    for alias in declared {
+        let toml::Value::String(alias_) = alias else {
+            anyhow::bail!("Key `{}` must be an array of strings!", ALIASES_KEY);
+        };
+
+        let url = format!("/{}", alias_.trim_start_matches('/'));
+        permalink::path_of(&url).context(format!("Invalid alias `{}`.", alias_))?;
+        if !aliases.contains(&url) {
+            aliases.push(url);
+        }
+    }
+
+    Ok(aliases)
+}
+
+// The page left at an alias, sending browsers (and search engines) to the url
+pub fn redirect_page(url: &str) -> String {
+    let url = escape(url);
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>Redirecting to {url}</title>\n<link rel=\"canonical\" href=\"{url}\">\n<meta http-equiv=\"refresh\" content=\"0; url={url}\">\n</head>\n<body>\n<a href=\"{url}\">Redirecting to {url}</a>\n</body>\n</html>\n"
+    )
+}
+
+// Write the _redirects file for every alias in the route tree
+pub async fn write_redirects_file(route: &Route, destination: &Path) -> Result<()> {
+    let mut lines = vec![];
+    collect(route, &mut lines);
+
+    let mut contents = lines.join("\n");
+    contents.push('\n');
+
+    util::paths::write_if_changed(&destination.join(REDIRECTS_FILE), &contents).await
+}
+
+fn collect(route: &Route, lines: &mut Vec<String>) {
+    match &route.details {
+        RouteDetails::Dir(dir) => {
+            for child in dir.children.iter() {
+                collect(child, lines);
+            }
+        }
+        RouteDetails::File(file) => {
+            let url = site::route_url(route);
+            for alias in file.aliases.iter() {
+                lines.push(format!("{} {} 301", alias, url));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::walker::route::{DirectoryRoute, FileRoute, RouteConfig};
+
+    fn aliases(text: &str) -> Result<Vec<String>> {
+        from_toml(&toml::from_str(text).unwrap())
+    }
+
+    #[test]
+    fn aliases_are_urls() {
+        assert_eq!(aliases("").unwrap(), Vec::<String>::new());
+        assert_eq!(
+            aliases(r#"aliases = ["old.html", "/older/path/", "//oldest"]"#).unwrap(),
+            vec!["/old.html", "/older/path/", "/oldest"]
+        );
+    }
+
+    #[test]
+    fn duplicates_count_once() {
+        assert_eq!(
+            aliases(r#"aliases = ["/old/", "old/", "/new"]"#).unwrap(),
+            vec!["/old/", "/new"]
+        );
+    }
+
+    #[test]
+    fn aliases_stay_in_the_site() {
+        for alias in ["../old.html", "/a/../../b", "/./a"] {
+            let text = format!("aliases = [\"{}\"]", alias);
+            assert!(aliases(&text).is_err(), "`{}` was accepted", alias);
+        }
+        assert!(aliases("aliases = [1]").is_err());
+        assert!(aliases(r#"aliases = "/old""#).is_err());
+    }
+
+    #[test]
+    fn redirect_pages_escape_the_url() {
+        let html = redirect_page("/a?b=1&c=\"2\"");
+        assert!(html.contains(r#"content="0; url=/a?b=1&amp;c=&quot;2&quot;""#));
+        assert!(!html.contains("&c"));
+    }
+
+    #[tokio::test]
+    async fn redirects_file() {
+        let page = |source: &str, aliases: &[&str]| Route {
+            config: RouteConfig::default(),
+            source: PathBuf::from(source),
+            details: RouteDetails::File(FileRoute {
+                path: PathBuf::from(source).with_extension("html"),
+                aliases: aliases.iter().map(|alias| alias.to_string()).collect(),
+                ..FileRoute::default()
+            }),
+        };
+        let route = Route {
+            config: RouteConfig::default(),
+            source: PathBuf::new(),
+            details: RouteDetails::Dir(DirectoryRoute {
+                children: vec![page("a.md", &["/old/a/", "/a.php"]), page("b.md", &[])],
+                path: PathBuf::new(),
+                feeds: vec![],
+            }),
+        };
+
+        let dir = tempfile::tempdir().unwrap();
+        write_redirects_file(&route, dir.path()).await.unwrap();
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join(REDIRECTS_FILE)).unwrap(),
+            "/old/a/ /a.html 301\n/a.php /a.html 301\n"
+        );
+    }
+}
@@ -0,0 +1,177 @@
+// Static assets are the files next to the pages that are not pages themselves,
+// such as images or stylesheets. They are copied (or hard linked) as they are
+// into the mirrored destination directory.
+//
+// Which files are assets is configured by the [assets] table of __common.toml:
+//
+//   [assets]
+//   include = ["*"]          # globs on the path relative to the source
+//   exclude = ["*.psd"]
+//   link = false             # hard link instead of copying
+//
+// Like the rest of the configuration, the table is inherited by subdirectories.
+
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+
+use crate::assert_toml_kind;
+
+const INCLUDE_KEY: &str = "include";
+const EXCLUDE_KEY: &str = "exclude";
+const LINK_KEY: &str = "link";
+
+// pages, their configuration and theme templates are never assets
+const ALWAYS_EXCLUDED: [&str; 3] = ["*.md", "*.toml", "*.hbs"];
+
+#[derive(Clone, Debug)]
+pub struct AssetRules {
+    include: Option<GlobSet>, // None includes everything
+    exclude: GlobSet,
+    pub link: bool,
+}
+
+#[allow(clippy::needless_arbitrary_self_type)]
+impl AssetRules {
+    pub fn from_toml(table: &toml::Table) -> Result<Self> {
+        let include = assert_toml_kind!(Array; table, INCLUDE_KEY)?;
+        let exclude = assert_toml_kind!(Array; table, EXCLUDE_KEY)?.unwrap_or_default();
+        let link = assert_toml_kind!(Boolean; table, LINK_KEY)?.unwrap_or(false);
+
+        let include = if let Some(include_) = include {
+            Some(glob_set(globs(&include_)?)?)
+        } else {
+            None
+        };
+
+        let mut exclude = globs(&exclude)?;
+        exclude.extend(ALWAYS_EXCLUDED.iter().map(|glob| glob.to_string()));
+
+        Ok(AssetRules {
+            include,
+            exclude: glob_set(exclude)?,
+            link,
+        })
+    }
+
+    // Whether the file at this path (relative to the source) is an asset
+    pub fn matches(self: &Self, relative: &str) -> bool {
+        let included = self
+            .include
+            .as_ref()
+            .is_none_or(|include| include.is_match(relative));
+
+        included && !self.exclude.is_match(relative)
+    }
+}
+
+// Copy or link an asset, replacing whatever is at the destination
+pub async fn publish(source: &PathBuf, destination: &PathBuf, link: bool) -> Result<()> {
+    if tokio::fs::try_exists(destination).await.unwrap_or(false) {
+        tokio::fs::remove_file(destination).await.context(format!(
+            "Failed to delete file `{}`.",
+            destination.display()
+        ))?;
+    }
+
+    if link && tokio::fs::hard_link(source, destination).await.is_ok() {
+        return Ok(());
+    }
+
+    tokio::fs::copy(source, destination)
+        .await
+        .context(format!("Failed to copy `{}`.", source.display()))?;
+
+    Ok(())
+}
+
+// A cheap fingerprint for assets, which may be large: their size and modification time
+pub async fn fingerprint(path: &PathBuf) -> Result<String> {
+    let metadata = tokio::fs::metadata(path)
+        .await
+        .context(format!("Failed to read metadata of `{}`.", path.display()))?;
+
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+        .map_or(0, |duration| duration.as_nanos());
+
+    Ok(format!("{}:{}", metadata.len(), modified))
+}
+
+fn globs(values: &[toml::Value]) -> Result<Vec<String>> {
+    values
+        .iter()
+        .map(|value| {
+            value
+                .as_str()
+                .map(|str| str.to_owned())
+                .context("Asset globs must be strings!")
+        })
+        .collect()
+}
+
+fn glob_set(globs: Vec<String>) -> Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for glob in globs {
+        builder.add(Glob::new(&glob).context(format!("Invalid glob `{}`.", glob))?);
+    }
+    builder.build().context("Failed to build glob set!")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(text: &str) -> Result<AssetRules> {
+        AssetRules::from_toml(&toml::from_str(text).unwrap())
+    }
+
+    #[test]
+    fn everything_but_pages_by_default() {
+        let rules = rules("").unwrap();
+        assert!(rules.matches("logo.png"));
+        assert!(rules.matches("talks/2024/slides.pdf"));
+        for page in ["index.md", "talks/__common.toml", "theme/main.hbs"] {
+            assert!(!rules.matches(page), "`{}` is an asset", page);
+        }
+        assert!(!rules.link);
+    }
+
+    #[test]
+    fn include_and_exclude() {
+        let rules = rules(
+            r#"
+            include = ["*.png", "static/**"]
+            exclude = ["*.psd", "static/drafts/**"]
+            link = true
+            "#,
+        )
+        .unwrap();
+
+        // globs match across directories
+        assert!(rules.matches("talks/logo.png"));
+        assert!(rules.matches("static/style.css"));
+        assert!(!rules.matches("slides.pdf"));
+        // excluding wins over including, and pages stay excluded
+        assert!(!rules.matches("static/art.psd"));
+        assert!(!rules.matches("static/drafts/a.png"));
+        assert!(!rules.matches("static/index.md"));
+        assert!(rules.link);
+    }
+
+    #[test]
+    fn an_empty_include_copies_nothing() {
+        assert!(!rules("include = []").unwrap().matches("logo.png"));
+    }
+
+    #[test]
+    fn bad_rules() {
+        assert!(rules("include = [1]").is_err());
+        assert!(rules(r#"exclude = ["a[b"]"#).is_err());
+        assert!(rules(r#"include = "*.png""#).is_err());
+        assert!(rules(r#"link = "yes""#).is_err());
+    }
+}
@@ -0,0 +1,90 @@
+// The dependency graph of a build: for every output file, the inputs that produced it,
+// along with fingerprints of their contents. It is persisted next to the destination
+// (see walker::scratch_paths), so that an incremental build can skip the outputs whose
+// inputs did not change, without the graph being deployed along with the site.
+
+use std::{collections::BTreeMap, path::Path};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::util;
+
+// input name (a path relative to the source, or a theme resource) -> fingerprint
+pub type Inputs = BTreeMap<String, String>;
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct DependencyGraph {
+    // output path relative to the destination -> its inputs
+    pub outputs: BTreeMap<String, Inputs>,
+}
+
+#[allow(clippy::needless_arbitrary_self_type)]
+impl DependencyGraph {
+    // Load the graph of the previous build, a missing or broken graph is just empty
+    pub async fn load(path: &Path) -> Self {
+        let Ok(contents) = util::paths::read(&path.to_path_buf()).await else {
+            return DependencyGraph::default();
+        };
+
+        toml::from_str(&contents).unwrap_or_else(|_| {
+            warn!("Ignoring malformed dependency graph `{}`.", path.display());
+            DependencyGraph::default()
+        })
+    }
+
+    pub async fn save(self: &Self, path: &Path) -> Result<()> {
+        let contents = toml::to_string(self).context("Failed to serialize dependency graph!")?;
+
+        util::paths::write_if_changed(&path.to_path_buf(), &contents).await
+    }
+
+    // An output is fresh if it was produced from exactly the same inputs
+    pub fn is_fresh(self: &Self, output: &str, inputs: &Inputs) -> bool {
+        self.outputs.get(output) == Some(inputs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn inputs(pairs: &[(&str, &str)]) -> Inputs {
+        pairs
+            .iter()
+            .map(|(name, hash)| (name.to_string(), hash.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn outputs_are_fresh_with_the_same_inputs() {
+        let mut graph = DependencyGraph::default();
+        let old = inputs(&[("a.md", "1"), ("__common.toml", "2")]);
+        graph.outputs.insert("a.html".to_owned(), old.clone());
+
+        assert!(graph.is_fresh("a.html", &old));
+        // a changed, added or removed input
+        assert!(!graph.is_fresh("a.html", &inputs(&[("a.md", "3"), ("__common.toml", "2")])));
+        assert!(!graph.is_fresh(
+            "a.html",
+            &inputs(&[("a.md", "1"), ("__common.toml", "2"), ("a.toml", "4")])
+        ));
+        assert!(!graph.is_fresh("a.html", &inputs(&[("a.md", "1")])));
+        // never built before
+        assert!(!graph.is_fresh("b.html", &old));
+    }
+
+    #[test]
+    fn graphs_survive_a_round_trip() {
+        let mut graph = DependencyGraph::default();
+        let old = inputs(&[("talks/a.md", "1"), ("dept/main.hbs", "2")]);
+        graph
+            .outputs
+            .insert("talks/a/index.html".to_owned(), old.clone());
+
+        let text = toml::to_string(&graph).unwrap();
+        let loaded: DependencyGraph = toml::from_str(&text).unwrap();
+        assert!(loaded.is_fresh("talks/a/index.html", &old));
+    }
+}
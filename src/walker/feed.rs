@@ -0,0 +1,372 @@
+// Atom and RSS feeds of a section. A directory whose own __common.toml sets
+// `feed = true` gets a feed.xml (Atom) listing the pages of its section, newest
+// first. `feed_formats = ["atom", "rss"]` picks the formats, rss.xml being RSS 2.0.
+//
+// Every entry is built from the `title`, `date` and `summary` of the page, and its
+// rendered content. Pages without a date are left out, since feeds are ordered by
+// it. `feed_limit = 20` keeps only the newest entries, and is inherited like the
+// rest of the config. Feeds link to absolute urls, so they need the base url of
+// the site.
+
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, FixedOffset};
+
+use super::{
+    route::{DirectoryRoute, Route, RouteDetails},
+    site::{self, DATE_KEY},
+};
+use crate::{assert_toml_kind, util, util::xml::escape};
+
+const FEED_KEY: &str = "feed";
+const FEED_FORMATS_KEY: &str = "feed_formats";
+const FEED_LIMIT_KEY: &str = "feed_limit";
+const SUMMARY_KEY: &str = "summary";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FeedFormat {
+    Atom,
+    Rss,
+}
+
+#[allow(clippy::needless_arbitrary_self_type)]
+impl FeedFormat {
+    // The feeds a directory asks for. The keys are taken out of the common table,
+    // since they are not inherited by the directories below it.
+    pub fn from_toml(common: &mut toml::Table) -> Result<Vec<FeedFormat>> {
+        let feed = assert_toml_kind!(Boolean; common, FEED_KEY)?.unwrap_or(false);
+        let formats = assert_toml_kind!(Array; common, FEED_FORMATS_KEY)?;
+        common.remove(FEED_KEY);
+        common.remove(FEED_FORMATS_KEY);
+
+        if !feed {
+            return Ok(vec![]);
+        }
+
+        let Some(formats_) = formats else {
+            return Ok(vec![FeedFormat::Atom]);
+        };
+
+        formats_
+            .iter()
+            .map(|format| match format.as_str() {
+                Some("atom") => Ok(FeedFormat::Atom),
+                Some("rss") => Ok(FeedFormat::Rss),
+                _ => anyhow::bail!("Unknown feed format `{}`, expected atom or rss.", format),
+            })
+            .collect()
+    }
+
+    pub fn file_name(self: &Self) -> &'static str {
+        match self {
+            FeedFormat::Atom => "feed.xml",
+            FeedFormat::Rss => "rss.xml",
+        }
+    }
+}
+
+struct Entry {
+    title: String,
+    url: String,
+    date: DateTime<FixedOffset>,
+    summary: Option<String>,
+    content: String,
+}
+
+// The feed of this (rendered) directory route in the given format. Returns the
+// path of the feed relative to the destination, and its contents.
+pub fn render(
+    route: &Route,
+    dir: &DirectoryRoute,
+    format: FeedFormat,
+    base_url: &str,
+) -> Result<(PathBuf, String)> {
+    let base_url = base_url.trim_end_matches('/');
+
+    let mut entries = vec![];
+    for page in site::section(&dir.children) {
+        let Some(date) = page.config.rest.get(DATE_KEY) else {
+            continue;
+        };
+        let RouteDetails::File(file) = &page.details else {
+            continue;
+        };
+
+        entries.push(Entry {
+            title: site::title(page),
+            url: format!("{}{}", base_url, site::route_url(page)),
+            date: util::date::from_toml(date)
+                .context(format!("In `{}`.", page.source.display()))?,
+            summary: assert_toml_kind!(String; page.config.rest, SUMMARY_KEY)?,
+            content: file.content_html.clone(),
+        });
+    }
+
+    // newest first
+    entries.sort_by_key(|entry| std::cmp::Reverse(entry.date));
+
+    let limit = assert_toml_kind!(Integer; route.config.rest, FEED_LIMIT_KEY)?.unwrap_or(0);
+    if limit > 0 {
+        entries.truncate(limit as usize);
+    }
+
+    let path = dir.path.join(format.file_name());
+    let feed_url = format!("{}{}", base_url, site::url(&path, false));
+    let section_url = format!("{}{}", base_url, site::url(&dir.path.join(""), false));
+
+    let title = dir
+        .children
+        .iter()
+        .find(|child| site::is_index(child))
+        .map_or_else(|| site::title(route), site::title);
+
+    let xml = match format {
+        FeedFormat::Atom => atom(&title, &feed_url, &section_url, &entries),
+        FeedFormat::Rss => rss(&title, &feed_url, &section_url, &entries),
+    };
+
+    Ok((path, xml))
+}
+
+fn atom(title: &str, feed_url: &str, section_url: &str, entries: &[Entry]) -> String {
+    // a feed without entries has not changed since the epoch, so that building the
+    // same site twice gives the same feed
+    let updated = entries
+        .first()
+        .map(|entry| entry.date)
+        .unwrap_or_default()
+        .to_rfc3339();
+
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+    xml.push_str("<feed xmlns=\"http://www.w3.org/2005/Atom\">\n");
+    xml.push_str(&format!("  <title>{}</title>\n", escape(title)));
+    xml.push_str(&format!(
+        "  <link href=\"{}\" rel=\"self\"/>\n",
+        escape(feed_url)
+    ));
+    xml.push_str(&format!("  <link href=\"{}\"/>\n", escape(section_url)));
+    xml.push_str(&format!("  <id>{}</id>\n", escape(feed_url)));
+    xml.push_str(&format!("  <updated>{}</updated>\n", updated));
+
+    for entry in entries {
+        xml.push_str("  <entry>\n");
+        xml.push_str(&format!("    <title>{}</title>\n", escape(&entry.title)));
+        xml.push_str(&format!("    <link href=\"{}\"/>\n", escape(&entry.url)));
+        xml.push_str(&format!("    <id>{}</id>\n", escape(&entry.url)));
+        xml.push_str(&format!(
+            "    <updated>{}</updated>\n",
+            entry.date.to_rfc3339()
+        ));
+        if let Some(summary) = &entry.summary {
+            xml.push_str(&format!("    <summary>{}</summary>\n", escape(summary)));
+        }
+        xml.push_str(&format!(
+            "    <content type=\"html\">{}</content>\n",
+            escape(&entry.content)
+        ));
+        xml.push_str("  </entry>\n");
+    }
+
+    xml.push_str("</feed>\n");
+    xml
+}
+
+fn rss(title: &str, feed_url: &str, section_url: &str, entries: &[Entry]) -> String {
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+    xml.push_str("<rss version=\"2.0\" xmlns:atom=\"http://www.w3.org/2005/Atom\">\n");
+    xml.push_str("  <channel>\n");
+    xml.push_str(&format!("    <title>{}</title>\n", escape(title)));
+    xml.push_str(&format!("    <link>{}</link>\n", escape(section_url)));
+    xml.push_str(&format!(
+        "    <atom:link href=\"{}\" rel=\"self\" type=\"application/rss+xml\"/>\n",
+        escape(feed_url)
+    ));
+    xml.push_str(&format!(
+        "    <description>{}</description>\n",
+        escape(title)
+    ));
+    if let Some(entry) = entries.first() {
+        xml.push_str(&format!(
+            "    <lastBuildDate>{}</lastBuildDate>\n",
+            entry.date.to_rfc2822()
+        ));
+    }
+
+    for entry in entries {
+        xml.push_str("    <item>\n");
+        xml.push_str(&format!("      <title>{}</title>\n", escape(&entry.title)));
+        xml.push_str(&format!("      <link>{}</link>\n", escape(&entry.url)));
+        xml.push_str(&format!("      <guid>{}</guid>\n", escape(&entry.url)));
+        xml.push_str(&format!(
+            "      <pubDate>{}</pubDate>\n",
+            entry.date.to_rfc2822()
+        ));
+        let description = entry.summary.as_ref().unwrap_or(&entry.content);
+        xml.push_str(&format!(
+            "      <description>{}</description>\n",
+            escape(description)
+        ));
+        xml.push_str("    </item>\n");
+    }
+
+    xml.push_str("  </channel>\n");
+    xml.push_str("</rss>\n");
+    xml
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::walker::route::{FileRoute, RouteConfig};
+
+    fn page(source: &str, config: &str, content: &str) -> Route {
+        Route {
+            config: RouteConfig {
+                rest: toml::from_str(config).unwrap(),
+                ..RouteConfig::default()
+            },
+            source: PathBuf::from(source),
+            details: RouteDetails::File(FileRoute {
+                path: PathBuf::from(source).with_extension("html"),
+                content_html: content.to_owned(),
+                ..FileRoute::default()
+            }),
+        }
+    }
+
+    fn section(config: &str, children: Vec<Route>) -> Route {
+        Route {
+            config: RouteConfig {
+                rest: toml::from_str(config).unwrap(),
+                ..RouteConfig::default()
+            },
+            source: PathBuf::from("talks"),
+            details: RouteDetails::Dir(DirectoryRoute {
+                children,
+                path: PathBuf::from("talks"),
+                feeds: vec![FeedFormat::Atom],
+            }),
+        }
+    }
+
+    fn talks(config: &str) -> Route {
+        section(
+            config,
+            vec![
+                page("talks/index.md", r#"title = "Talks""#, ""),
+                page(
+                    "talks/a.md",
+                    r#"title = "A & B"
+                    date = "2024-01-01T00:00:00+05:00"
+                    summary = "About <a>""#,
+                    "<p>a</p>",
+                ),
+                page(
+                    "talks/b.md",
+                    r#"title = "B"
+                    date = "2023-12-31T23:00:00Z""#,
+                    "<p>b</p>",
+                ),
+                page("talks/c.md", "date = 2024-03-01T10:00:00Z", "<p>c</p>"),
+                page("talks/undated.md", r#"title = "Undated""#, ""),
+            ],
+        )
+    }
+
+    fn render_feed(route: &Route, format: FeedFormat) -> Result<(PathBuf, String)> {
+        let RouteDetails::Dir(dir) = &route.details else {
+            unreachable!()
+        };
+        render(route, dir, format, "https://x.org/")
+    }
+
+    fn positions(xml: &str, parts: &[&str]) -> Vec<usize> {
+        parts.iter().map(|part| xml.find(part).unwrap()).collect()
+    }
+
+    #[test]
+    fn atom_entries_are_newest_first() {
+        let (path, xml) = render_feed(&talks(""), FeedFormat::Atom).unwrap();
+        assert_eq!(path, PathBuf::from("talks/feed.xml"));
+
+        assert!(xml.contains("<title>Talks</title>"));
+        assert!(xml.contains(r#"<link href="https://x.org/talks/feed.xml" rel="self"/>"#));
+        assert!(xml.contains("<updated>2024-03-01T10:00:00+00:00</updated>\n  <entry>"));
+        // by time, not by the text of the dates
+        let order = positions(
+            &xml,
+            &[
+                "https://x.org/talks/c.html",
+                "<title>B</title>",
+                "A &amp; B",
+            ],
+        );
+        assert!(order.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(xml.contains("<summary>About &lt;a&gt;</summary>"));
+        assert!(xml.contains(r#"<content type="html">&lt;p&gt;a&lt;/p&gt;</content>"#));
+        assert!(!xml.contains("Undated"));
+    }
+
+    #[test]
+    fn rss_entries() {
+        let (path, xml) = render_feed(&talks(""), FeedFormat::Rss).unwrap();
+        assert_eq!(path, PathBuf::from("talks/rss.xml"));
+
+        assert!(xml.contains("<rss version=\"2.0\""));
+        assert!(xml.contains("<lastBuildDate>Fri, 1 Mar 2024 10:00:00 +0000</lastBuildDate>"));
+        assert!(xml.contains("<pubDate>Mon, 1 Jan 2024 00:00:00 +0500</pubDate>"));
+        // the summary stands in for the content
+        assert!(xml.contains("<description>About &lt;a&gt;</description>"));
+        assert!(xml.contains("<description>&lt;p&gt;b&lt;/p&gt;</description>"));
+        assert_eq!(xml.matches("<item>").count(), 3);
+    }
+
+    #[test]
+    fn feed_limit_keeps_the_newest() {
+        let (_, xml) = render_feed(&talks("feed_limit = 2"), FeedFormat::Atom).unwrap();
+        assert_eq!(xml.matches("<entry>").count(), 2);
+        assert!(!xml.contains("A &amp; B"));
+
+        let (_, xml) = render_feed(&talks("feed_limit = 0"), FeedFormat::Atom).unwrap();
+        assert_eq!(xml.matches("<entry>").count(), 3);
+
+        assert!(render_feed(&talks(r#"feed_limit = "2""#), FeedFormat::Atom).is_err());
+    }
+
+    #[test]
+    fn empty_feeds_are_reproducible() {
+        let route = section("", vec![]);
+        let (_, xml) = render_feed(&route, FeedFormat::Atom).unwrap();
+        assert!(xml.contains("<updated>1970-01-01T00:00:00+00:00</updated>"));
+        assert_eq!(xml, render_feed(&route, FeedFormat::Atom).unwrap().1);
+    }
+
+    #[test]
+    fn bad_dates_name_the_page() {
+        let route = section("", vec![page("talks/a.md", r#"date = "soon""#, "")]);
+        let err = render_feed(&route, FeedFormat::Atom).unwrap_err();
+        assert!(err.to_string().contains("talks/a.md"));
+    }
+
+    #[test]
+    fn formats() {
+        let formats = |text: &str| {
+            let mut common = toml::from_str::<toml::Table>(text).unwrap();
+            let formats = FeedFormat::from_toml(&mut common)?;
+            assert!(!common.contains_key(FEED_KEY) && !common.contains_key(FEED_FORMATS_KEY));
+            Ok::<_, anyhow::Error>(formats)
+        };
+
+        assert_eq!(formats("").unwrap(), vec![]);
+        assert_eq!(formats("feed = false").unwrap(), vec![]);
+        assert_eq!(formats("feed = true").unwrap(), vec![FeedFormat::Atom]);
+        assert_eq!(
+            formats("feed = true\nfeed_formats = [\"rss\", \"atom\"]").unwrap(),
+            vec![FeedFormat::Rss, FeedFormat::Atom]
+        );
+        assert!(formats("feed = true\nfeed_formats = [\"json\"]").is_err());
+        assert!(formats("feed = true\nfeed_formats = \"rss\"").is_err());
+        assert!(formats("feed = \"yes\"").is_err());
+    }
+}
@@ -0,0 +1,136 @@
+// Ignore rules for the source walk, in the style of .gitignore. Any directory can
+// declare rules in a .ferneignore file, or in an `ignore` array of its __common.toml.
+// Rules are relative to the directory declaring them and apply to everything below
+// it, with the rules of deeper directories taking precedence.
+
+use std::{path::PathBuf, sync::Arc};
+
+use anyhow::{Context, Result};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+
+use crate::{assert_toml_kind, util};
+
+pub const IGNORE_FILE: &str = ".ferneignore";
+const IGNORE_KEY: &str = "ignore";
+
+#[derive(Clone, Debug, Default)]
+pub struct IgnoreRules {
+    levels: Vec<Arc<Gitignore>>, // outermost directory first
+}
+
+#[allow(clippy::needless_arbitrary_self_type)]
+impl IgnoreRules {
+    // Add the rules declared in this directory. The `ignore` key is taken out of
+    // the common table, since it is not page data.
+    pub async fn extend(self: &Self, dir: &PathBuf, common: &mut toml::Table) -> Result<Self> {
+        let patterns = assert_toml_kind!(Array; common, IGNORE_KEY)?.unwrap_or_default();
+        common.remove(IGNORE_KEY);
+
+        let file_path = dir.join(IGNORE_FILE);
+        let file = util::paths::read(&file_path).await.unwrap_or_default();
+
+        if patterns.is_empty() && file.is_empty() {
+            return Ok(self.clone());
+        }
+
+        let mut builder = GitignoreBuilder::new(dir);
+
+        for line in file.lines() {
+            builder
+                .add_line(Some(file_path.clone()), line)
+                .context(format!("Invalid ignore rule `{}`.", line))?;
+        }
+
+        for pattern in patterns {
+            let pattern = pattern.as_str().context("Ignore rules must be strings!")?;
+            builder
+                .add_line(None, pattern)
+                .context(format!("Invalid ignore rule `{}`.", pattern))?;
+        }
+
+        let gitignore = builder.build().context(format!(
+            "Failed to build ignore rules for `{}`.",
+            dir.display()
+        ))?;
+
+        let mut levels = self.levels.clone();
+        levels.push(Arc::new(gitignore));
+
+        Ok(IgnoreRules { levels })
+    }
+
+    pub fn is_ignored(self: &Self, path: &PathBuf, is_dir: bool) -> bool {
+        // the innermost rule that matches decides, whitelisting (!) included
+        for gitignore in self.levels.iter().rev() {
+            let matched = gitignore.matched(path, is_dir);
+            if matched.is_ignore() {
+                return true;
+            } else if matched.is_whitelist() {
+                return false;
+            }
+        }
+
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn extend(rules: &IgnoreRules, dir: &std::path::Path, common: &str) -> IgnoreRules {
+        let mut common = toml::from_str::<toml::Table>(common).unwrap();
+        let rules = rules.extend(&dir.to_path_buf(), &mut common).await.unwrap();
+        assert!(!common.contains_key(IGNORE_KEY));
+        rules
+    }
+
+    #[tokio::test]
+    async fn rules_of_files_and_keys() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        std::fs::write(root.join(IGNORE_FILE), "# drafts\n*.bak\nnotes/\n").unwrap();
+
+        let rules = extend(&IgnoreRules::default(), root, r#"ignore = ["/private.md"]"#).await;
+
+        assert!(rules.is_ignored(&root.join("a.bak"), false));
+        assert!(rules.is_ignored(&root.join("talks/b.bak"), false));
+        assert!(rules.is_ignored(&root.join("notes"), true));
+        // a directory rule does not match files, and / anchors to the directory
+        assert!(!rules.is_ignored(&root.join("notes"), false));
+        assert!(rules.is_ignored(&root.join("private.md"), false));
+        assert!(!rules.is_ignored(&root.join("talks/private.md"), false));
+        assert!(!rules.is_ignored(&root.join("a.md"), false));
+    }
+
+    #[tokio::test]
+    async fn deeper_rules_win() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let talks = root.join("talks");
+
+        let outer = extend(&IgnoreRules::default(), root, r#"ignore = ["*.pdf"]"#).await;
+        let inner = extend(&outer, &talks, r#"ignore = ["!slides.pdf", "*.txt"]"#).await;
+
+        // negation in a deeper directory brings a file back, but only there
+        assert!(!inner.is_ignored(&talks.join("slides.pdf"), false));
+        assert!(inner.is_ignored(&talks.join("other.pdf"), false));
+        assert!(outer.is_ignored(&root.join("slides.pdf"), false));
+        assert!(inner.is_ignored(&talks.join("a.txt"), false));
+        assert!(!outer.is_ignored(&root.join("a.txt"), false));
+    }
+
+    #[tokio::test]
+    async fn no_rules() {
+        let dir = tempfile::tempdir().unwrap();
+        let rules = extend(&IgnoreRules::default(), dir.path(), "").await;
+        assert!(rules.levels.is_empty());
+
+        let mut common = toml::from_str::<toml::Table>("ignore = [1]").unwrap();
+        let path = dir.path().to_path_buf();
+        assert!(IgnoreRules::default()
+            .extend(&path, &mut common)
+            .await
+            .is_err());
+    }
+}
@@ -0,0 +1,82 @@
+// A machine readable list of everything a build produced, written to the
+// destination as ferne-manifest.json for deploy tooling and link checkers.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+
+use super::route::{Route, RouteDetails};
+use crate::util;
+
+pub const MANIFEST_FILE: &str = "ferne-manifest.json";
+
+#[derive(Serialize)]
+struct Manifest {
+    routes: Vec<ManifestRoute>,
+}
+
+#[derive(Serialize)]
+struct ManifestRoute {
+    #[serde(rename = "type")]
+    kind: &'static str, // "directory" or "page"
+    source: String,
+    output: String,
+    theme: ManifestTheme,
+    config: serde_json::Map<String, serde_json::Value>,
+}
+
+#[derive(Serialize)]
+struct ManifestTheme {
+    name: String,
+    kind: String,
+    config: serde_json::Map<String, serde_json::Value>,
+}
+
+// Write the manifest of the route tree into the destination
+pub async fn write(route: &Route, destination: &Path) -> Result<()> {
+    let mut routes = vec![];
+    collect(route, &mut routes);
+
+    let contents = serde_json::to_string_pretty(&Manifest { routes })
+        .context("Failed to serialize manifest!")?;
+
+    util::paths::write_if_changed(&destination.join(MANIFEST_FILE), &contents).await
+}
+
+// Flatten the tree, parents before their children
+fn collect(route: &Route, routes: &mut Vec<ManifestRoute>) {
+    let (kind, output) = match &route.details {
+        RouteDetails::Dir(dir) => ("directory", &dir.path),
+        RouteDetails::File(file) => ("page", &file.path),
+    };
+
+    let theme = &route.config.theme;
+
+    let entry = |output: &PathBuf| ManifestRoute {
+        kind,
+        source: util::paths::path_name(&route.source),
+        output: util::paths::path_name(output),
+        theme: ManifestTheme {
+            name: theme.name.clone(),
+            kind: theme.kind.clone(),
+            config: util::toml::table_to_json(&theme.rest),
+        },
+        config: util::toml::table_to_json(&route.config.rest),
+    };
+
+    routes.push(entry(output));
+
+    // every page of a paginated listing is an output of its own
+    if let RouteDetails::File(file) = &route.details {
+        for (path, _) in file.later_pages.iter() {
+            routes.push(entry(path));
+        }
+    }
+
+    if let RouteDetails::Dir(dir) = &route.details {
+        for child in dir.children.iter() {
+            collect(child, routes);
+        }
+    }
+}
@@ -1,8 +1,22 @@
 // Walk the source directory, and parse the directories / files
 // into the destination directory.
 
+mod aliases;
+mod assets;
+mod deps;
+mod feed;
+mod ignores;
+mod manifest;
+mod permalink;
+mod render;
 mod route;
+mod site;
+mod sitemap;
+mod taxonomy;
+#[allow(clippy::module_inception)]
 mod walker;
 
-pub use route::{RouteConfig, ThemeConfig};
+pub use render::ROOT_KEY;
+pub use route::{Route, RouteConfig, RouteDetails, ThemeConfig};
+pub use site::compare;
 pub use walker::*;
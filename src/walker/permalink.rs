@@ -0,0 +1,214 @@
+// Where a page is written to. By default that is <stem>.html next to its source
+// (or <stem>/index.html with pretty urls), but a page can set its own `slug` to use
+// instead of the stem, and a `permalink` decides the whole path. A permalink set in
+// a __common.toml is a pattern for every page below it, such as
+//
+//   permalink = "/talks/:year/:slug/"
+//
+// with :year, :month and :day taken from the `date` of the page, :slug being the
+// slug (or stem) and :title the slugified title. A page missing what a placeholder
+// needs is an error. A permalink ending in / is written to the index.html in that
+// directory, and linked to by the url of the directory. Index pages always stay
+// where they are.
+
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::{Context, Result};
+use chrono::Datelike;
+use once_cell::sync::Lazy;
+use regex::{Captures, Regex};
+
+use super::site::{self, DATE_KEY, PERMALINK_KEY, TITLE_KEY};
+use crate::{assert_toml_kind, util};
+
+const SLUG_KEY: &str = "slug";
+
+// placeholders look like :name
+const PLACEHOLDER_REGEX_SPEC: &str = r":([a-z]+)";
+
+// The output path of a page, relative to the destination. The directory is the
+// one mirroring the source directory of the page, own is the config the page
+// declares itself, and config is the one merged from all levels.
+pub fn output_path(
+    dir: &Path,
+    stem: &str,
+    own: &toml::Table,
+    config: &toml::Table,
+) -> Result<PathBuf> {
+    if stem == site::INDEX_STEM {
+        return Ok(dir.join(site::INDEX_FILE));
+    }
+
+    // a slug only makes sense for a single page, so it is never inherited
+    let slug = assert_toml_kind!(String; own, SLUG_KEY)?.unwrap_or(stem.to_owned());
+    check_slug(&slug)?;
+
+    let Some(pattern) = assert_toml_kind!(String; config, PERMALINK_KEY)? else {
+        return Ok(if site::is_pretty(config) {
+            dir.join(&slug).join(site::INDEX_FILE)
+        } else {
+            dir.join(format!("{}.html", slug))
+        });
+    };
+
+    let expanded = expand(&pattern, &slug, config)
+        .context(format!("Failed to expand permalink `{}`.", pattern))?;
+
+    path_of(&expanded).context(format!("Invalid permalink `{}`.", pattern))
+}
+
+// The path of the page served at this url, relative to the destination. A url
+// ending in / is served by the index.html of that directory, and one without an
+// extension by the .html file of that name.
+pub fn path_of(url: &str) -> Result<PathBuf> {
+    let mut path = PathBuf::from(url.trim_start_matches('/'));
+    if url.ends_with('/') {
+        path.push(site::INDEX_FILE);
+    } else if path.extension().is_none() {
+        path.set_extension("html");
+    }
+
+    let escapes = path
+        .components()
+        .any(|component| !matches!(component, Component::Normal(_)));
+    if escapes || path.as_os_str().is_empty() {
+        anyhow::bail!("`{}` does not name a page in the site!", url);
+    }
+
+    Ok(path)
+}
+
+// A slug names a single file or directory, which stays next to the page
+fn check_slug(slug: &str) -> Result<()> {
+    let invalid = slug.is_empty()
+        || slug == "."
+        || slug == ".."
+        || slug.contains(['/', '\\'])
+        || Path::new(slug).is_absolute();
+    if invalid {
+        anyhow::bail!("`{}` is not a valid slug!", slug);
+    }
+
+    Ok(())
+}
+
+fn expand(pattern: &str, slug: &str, config: &toml::Table) -> Result<String> {
+    static PLACEHOLDER_REGEX: Lazy<Regex> =
+        Lazy::new(|| Regex::new(PLACEHOLDER_REGEX_SPEC).unwrap());
+
+    let date = match config.get(DATE_KEY) {
+        Some(value) => Some(util::date::from_toml(value)?),
+        None => None,
+    };
+    let title = assert_toml_kind!(String; config, TITLE_KEY)?;
+
+    let mut error = None;
+
+    let expanded = PLACEHOLDER_REGEX.replace_all(pattern, |captures: &Captures| {
+        let name = &captures[1];
+
+        let value = match (name, &date) {
+            ("slug", _) => Some(slug.to_owned()),
+            ("title", _) => {
+                // an empty title would leave the page at the url of its directory
+                let slug = title
+                    .as_deref()
+                    .map(util::slug::slugify)
+                    .unwrap_or_default();
+                if slug.is_empty() {
+                    error = Some(format!(
+                        "`:{}` needs the page to have a title with letters or digits.",
+                        name
+                    ));
+                }
+                Some(slug)
+            }
+            ("year", Some(date_)) => Some(format!("{:04}", date_.year())),
+            ("month", Some(date_)) => Some(format!("{:02}", date_.month())),
+            ("day", Some(date_)) => Some(format!("{:02}", date_.day())),
+            ("year" | "month" | "day", None) => {
+                error = Some(format!("`:{}` needs the page to have a date.", name));
+                None
+            }
+            _ => {
+                error = Some(format!("Unknown placeholder `:{}`.", name));
+                None
+            }
+        };
+
+        value.unwrap_or_default()
+    });
+
+    match error {
+        Some(error_) => anyhow::bail!(error_),
+        None => Ok(expanded.into_owned()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table(text: &str) -> toml::Table {
+        toml::from_str(text).unwrap()
+    }
+
+    #[test]
+    fn slug_replaces_the_stem() {
+        let own = table(r#"slug = "spectral""#);
+        let path = output_path(Path::new("talks"), "one", &own, &own).unwrap();
+        assert_eq!(path, PathBuf::from("talks/spectral.html"));
+    }
+
+    #[test]
+    fn slug_cannot_leave_its_directory() {
+        for slug in ["../../pwned", "..", ".", "", "a/b", "/etc/passwd", "a\\b"] {
+            let own = table(&format!("slug = {:?}", slug));
+            let result = output_path(Path::new("talks"), "one", &own, &own);
+            assert!(result.is_err(), "slug `{}` was accepted", slug);
+        }
+    }
+
+    #[test]
+    fn permalink_expands_placeholders() {
+        let config = table(
+            r#"
+            permalink = "/talks/:year/:month/:slug/"
+            date = "2024-03-09"
+            "#,
+        );
+        let path = output_path(Path::new("archive"), "spring", &table(""), &config).unwrap();
+        assert_eq!(path, PathBuf::from("talks/2024/03/spring/index.html"));
+    }
+
+    #[test]
+    fn permalink_needs_a_date_for_dates() {
+        let config = table(r#"permalink = "/:year/:slug""#);
+        assert!(output_path(Path::new(""), "a", &table(""), &config).is_err());
+    }
+
+    #[test]
+    fn permalink_needs_a_title_for_titles() {
+        let config = |extra: &str| table(&format!("permalink = \"/talks/:title/\"\n{}", extra));
+        let path = |config: &toml::Table| output_path(Path::new(""), "a", &table(""), config);
+
+        assert_eq!(
+            path(&config(r#"title = "Spectral Theory""#)).unwrap(),
+            PathBuf::from("talks/spectral-theory/index.html")
+        );
+        // never the index.html of the section
+        for extra in ["", r#"title = """#, r#"title = "?!""#] {
+            let err = path(&config(extra)).unwrap_err();
+            assert!(format!("{:#}", err).contains("`:title`"), "{:#}", err);
+        }
+    }
+
+    #[test]
+    fn path_of_urls() {
+        assert_eq!(path_of("/a/b/").unwrap(), PathBuf::from("a/b/index.html"));
+        assert_eq!(path_of("/a/b").unwrap(), PathBuf::from("a/b.html"));
+        assert_eq!(path_of("/a/feed.xml").unwrap(), PathBuf::from("a/feed.xml"));
+        assert!(path_of("/../a").is_err());
+        assert!(path_of("").is_err());
+    }
+}
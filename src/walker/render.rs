@@ -0,0 +1,485 @@
+// The second phase of a build. The walk collects the whole route tree first, so
+// that when the pages are rendered here, every template can see all of it.
+
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::{Context, Result};
+use async_recursion::async_recursion;
+use tracing::info;
+
+use super::{
+    aliases,
+    deps::Inputs,
+    feed::{self, FeedFormat},
+    permalink,
+    route::{DirectoryRoute, Route, RouteDetails},
+    site,
+    walker::{relative_name, BuildOptions, BuildState},
+};
+use crate::{
+    assert_toml_kind,
+    config::{SiteConfig, BASE_URL_KEY},
+    theme::{
+        RenderedContent, TemplateRegistry, PAGES_SLOT, PAGE_SLOT, PAGINATOR_SLOT, SITE_SLOT,
+        URL_SLOT,
+    },
+    util,
+};
+
+// pseudo-input recorded for every page, since every page sees the navigation tree
+const SITE_INPUT: &str = "__site__";
+// and for index pages, which see the pages of their section
+const PAGES_INPUT: &str = "__pages__";
+// and for generated pages, which see the data they were generated with
+const DATA_INPUT: &str = "__data__";
+
+// key of the site data seen by templates, next to the settings of the site config
+pub const ROOT_KEY: &str = "root";
+
+// how many pages of a listing go on one page, set for the listing page
+const PAGINATE_KEY: &str = "paginate";
+
+// the later pages of a paginated listing go to <dir>/page/<number>/index.html
+const PAGINATION_DIR: &str = "page";
+
+#[derive(Clone, Debug)]
+pub struct Renderer {
+    registry: TemplateRegistry,
+    options: BuildOptions,
+    state: Arc<BuildState>,
+
+    site: Arc<serde_json::Value>, // the navigation tree
+    site_fingerprint: String,
+
+    pages: Option<Arc<serde_json::Value>>, // the pages of the section, for its index page
+    feed_pages: Arc<HashSet<PathBuf>>,     // sources of the pages listed in some feed
+}
+
+#[allow(clippy::needless_arbitrary_self_type)]
+impl Renderer {
+    pub fn new(
+        registry: TemplateRegistry,
+        options: BuildOptions,
+        state: Arc<BuildState>,
+        config: &SiteConfig,
+        root: &Route,
+    ) -> Self {
+        // the settings of the site, along with its navigation tree
+        let mut site = util::toml::table_to_json(&config.settings);
+        site.insert(BASE_URL_KEY.to_owned(), options.base_url.clone().into());
+        site.insert(ROOT_KEY.to_owned(), site::tree(root));
+        let site = serde_json::Value::Object(site);
+        let site_fingerprint = util::hash::fingerprint(site.to_string().as_bytes());
+
+        let mut feed_pages = HashSet::new();
+        collect_feed_pages(root, &mut feed_pages);
+
+        Renderer {
+            registry,
+            options,
+            state,
+            site: Arc::new(site),
+            site_fingerprint,
+            pages: None,
+            feed_pages: Arc::new(feed_pages),
+        }
+    }
+
+    // The renderer for the index page among these children, if there is one
+    fn for_index(self: &Self, children: &[Route]) -> Result<Option<Self>> {
+        let Some(index) = children.iter().find(|child| site::is_index(child)) else {
+            return Ok(None);
+        };
+
+        let mut pages = site::pages(children);
+        site::sort(&mut pages, &index.config.rest)?;
+
+        Ok(Some(Renderer {
+            pages: Some(Arc::new(serde_json::Value::Array(pages))),
+            ..self.clone()
+        }))
+    }
+}
+
+// The sources of the pages whose content goes into a feed, see feed::render
+fn collect_feed_pages(route: &Route, feed_pages: &mut HashSet<PathBuf>) {
+    let RouteDetails::Dir(dir) = &route.details else {
+        return;
+    };
+
+    if !dir.feeds.is_empty() {
+        feed_pages.extend(site::section(&dir.children).map(|page| page.source.clone()));
+    }
+
+    for child in dir.children.iter() {
+        collect_feed_pages(child, feed_pages);
+    }
+}
+
+// Render (and write) every page below this route
+#[async_recursion]
+pub async fn render_route(mut route: Route, renderer: Renderer) -> Result<Route> {
+    match route.details {
+        RouteDetails::Dir(ref mut dir) => {
+            let mut children_tasks = tokio::task::JoinSet::new();
+
+            let index_renderer = renderer.for_index(&dir.children)?;
+
+            for child in dir.children.drain(..) {
+                let renderer_ = match &index_renderer {
+                    Some(index_renderer_) if site::is_index(&child) => index_renderer_.clone(),
+                    _ => Renderer {
+                        pages: None,
+                        ..renderer.clone()
+                    },
+                };
+                children_tasks.spawn(render_route(child, renderer_));
+            }
+
+            while let Some(result) = children_tasks.join_next().await {
+                let child = result.context(format!(
+                    "Failed to finish join for subpath `{}`!",
+                    route.source.display()
+                ))??;
+                dir.children.push(child);
+            }
+
+            // the children finish in any order, keep them in a stable one
+            dir.children.sort_by(|a, b| a.source.cmp(&b.source));
+        }
+        RouteDetails::File(_) => {
+            render_file(&mut route, &renderer).await?;
+        }
+    }
+
+    // feeds need the rendered pages of the section
+    if let RouteDetails::Dir(dir) = &route.details {
+        for format in dir.feeds.iter() {
+            write_feed(&route, dir, *format, &renderer).await?;
+        }
+    }
+
+    Ok(route)
+}
+
+// Feeds are cheap to produce, so they are always written (if changed)
+async fn write_feed(
+    route: &Route,
+    dir: &DirectoryRoute,
+    format: FeedFormat,
+    renderer: &Renderer,
+) -> Result<()> {
+    let base_url = renderer.options.base_url.as_ref().context(format!(
+        "The feed of `{}` needs the base url of the site, set it with --base-url.",
+        route.source.display()
+    ))?;
+
+    let (feed_path, xml) = feed::render(route, dir, format, base_url)?;
+
+    let path = renderer.state.destination.join(&feed_path);
+    let output = relative_name(&renderer.state.destination, &path);
+    util::paths::write_if_changed(&path, &xml).await?;
+
+    // recorded with the feed itself as its input, so that --prune knows about it
+    let mut inputs = Inputs::new();
+    inputs.insert(output.clone(), util::hash::fingerprint(xml.as_bytes()));
+    renderer
+        .state
+        .current
+        .lock()
+        .await
+        .outputs
+        .insert(output, inputs);
+
+    Ok(())
+}
+
+// Leave a page at the alias that redirects to the url
+async fn write_redirect(alias: &str, url: &str, source: &Path, renderer: &Renderer) -> Result<()> {
+    let target = match &renderer.options.base_url {
+        Some(base_url) => format!("{}{}", base_url.trim_end_matches('/'), url),
+        None => url.to_owned(),
+    };
+    let html = aliases::redirect_page(&target);
+
+    let alias_path =
+        permalink::path_of(alias).context(format!("Invalid alias in `{}`.", source.display()))?;
+    let path = renderer.state.destination.join(alias_path);
+    let output = relative_name(&renderer.state.destination, &path);
+
+    if let Some(parent) = path.parent() {
+        util::dir::create(&parent.to_path_buf()).await;
+    }
+    util::paths::write_if_changed(&path, &html).await?;
+
+    let mut inputs = Inputs::new();
+    inputs.insert(output.clone(), util::hash::fingerprint(html.as_bytes()));
+    let previous = renderer
+        .state
+        .current
+        .lock()
+        .await
+        .outputs
+        .insert(output.clone(), inputs);
+
+    if previous.is_some() {
+        anyhow::bail!(
+            "The alias `{}` of `{}` is written to `{}`, which another page is written to too!",
+            alias,
+            source.display(),
+            output
+        );
+    }
+
+    Ok(())
+}
+
+async fn render_file(route: &mut Route, renderer: &Renderer) -> Result<()> {
+    let page = site::page(route);
+    let url = site::route_url(route);
+
+    let Route {
+        config,
+        source,
+        details: RouteDetails::File(file),
+    } = route
+    else {
+        return Ok(());
+    };
+
+    file.inputs
+        .insert(SITE_INPUT.to_owned(), renderer.site_fingerprint.clone());
+    if let Some(pages) = &renderer.pages {
+        file.inputs.insert(
+            PAGES_INPUT.to_owned(),
+            util::hash::fingerprint(pages.to_string().as_bytes()),
+        );
+    }
+    if !file.data.is_empty() {
+        let data = serde_json::Value::Object(file.data.clone());
+        file.inputs.insert(
+            DATA_INPUT.to_owned(),
+            util::hash::fingerprint(data.to_string().as_bytes()),
+        );
+    }
+
+    // generated pages may bring their own listing, instead of their section's
+    let mut data = file.data.clone();
+    let pages = data
+        .remove(PAGES_SLOT)
+        .or_else(|| renderer.pages.as_deref().cloned());
+
+    let per_page = assert_toml_kind!(Integer; config.rest, PAGINATE_KEY)?.unwrap_or(0);
+
+    // a paginated listing is rendered once for every chunk of its pages
+    let views = match pages {
+        Some(pages_) if per_page > 0 => paginate(
+            &pages_,
+            per_page as usize,
+            &file.path,
+            site::is_pretty(&config.rest),
+        ),
+        Some(pages_) => vec![(file.path.clone(), pages_, None)],
+        None => vec![(file.path.clone(), serde_json::Value::Null, None)],
+    };
+
+    let mut rendered = vec![];
+
+    for (view_path, pages, paginator) in views {
+        let path = renderer.state.destination.join(&view_path);
+        let output = relative_name(&renderer.state.destination, &path);
+
+        let fresh = renderer.options.incremental
+            && renderer.state.previous.is_fresh(&output, &file.inputs)
+            && tokio::fs::try_exists(&path).await.unwrap_or(false);
+
+        let mut globals = data.clone();
+        globals.insert(
+            URL_SLOT.to_owned(),
+            site::url(&view_path, site::is_pretty(&config.rest)).into(),
+        );
+        globals.insert(SITE_SLOT.to_owned(), (*renderer.site).clone());
+        globals.insert(PAGE_SLOT.to_owned(), page.clone());
+        if !pages.is_null() {
+            globals.insert(PAGES_SLOT.to_owned(), pages);
+        }
+        if let Some(paginator_) = paginator {
+            globals.insert(PAGINATOR_SLOT.to_owned(), paginator_);
+        }
+
+        // an unchanged page is only rendered for the content a feed includes
+        let content = if !fresh || renderer.feed_pages.contains(source) {
+            renderer
+                .registry
+                .render_content(&file.content, config, &globals)
+                .await
+                .context(format!("Failed to render `{}`.", source.display()))?
+        } else {
+            RenderedContent::default()
+        };
+
+        let html = if fresh {
+            // nothing changed, reuse the page written by the last build
+            info!("Skipping unchanged file `{}`", path.display());
+            util::paths::read(&path).await?
+        } else {
+            let html = renderer
+                .registry
+                .clone()
+                .render_template(content.clone(), config, &globals)
+                .await
+                .context(format!("Failed to render `{}`.", source.display()))?;
+
+            // Write to file, later pages of a listing go into directories of their own
+            if let Some(parent) = path.parent() {
+                util::dir::create(&parent.to_path_buf()).await;
+            }
+            util::paths::write_if_changed(&path, &html).await?;
+
+            html
+        };
+
+        let previous = renderer
+            .state
+            .current
+            .lock()
+            .await
+            .outputs
+            .insert(output.clone(), file.inputs.clone());
+
+        // with slugs and permalinks, two pages can ask for the same path
+        if previous.is_some() {
+            anyhow::bail!(
+                "`{}` is written to `{}`, which another page is written to too!",
+                source.display(),
+                output
+            );
+        }
+
+        rendered.push((view_path, html, content.main));
+    }
+
+    for alias in file.aliases.iter() {
+        write_redirect(alias, &url, source, renderer).await?;
+    }
+
+    let mut rendered = rendered.into_iter();
+    (_, file.html, file.content_html) = rendered.next().unwrap();
+    file.later_pages = rendered.map(|(path, html, _)| (path, html)).collect();
+
+    Ok(())
+}
+
+// Split the pages of a section into chunks. The first chunk is rendered at the
+// path of the index page, and the k-th one at page/k/index.html next to it.
+// Returns the path, the pages and the paginator data of every chunk.
+fn paginate(
+    pages: &serde_json::Value,
+    per_page: usize,
+    index_path: &Path,
+    pretty: bool,
+) -> Vec<(PathBuf, serde_json::Value, Option<serde_json::Value>)> {
+    let pages = pages.as_array().cloned().unwrap_or_default();
+    let chunks = pages.chunks(per_page).collect::<Vec<_>>();
+    let total = chunks.len().max(1);
+
+    let dir = index_path.parent().map(PathBuf::from).unwrap_or_default();
+    let path = |number: usize| {
+        if number == 1 {
+            index_path.to_path_buf()
+        } else {
+            dir.join(PAGINATION_DIR)
+                .join(number.to_string())
+                .join(index_path.file_name().unwrap_or_default())
+        }
+    };
+    let url = |number: usize| serde_json::Value::String(site::url(&path(number), pretty));
+
+    (1..=total)
+        .map(|number| {
+            let paginator = serde_json::json!({
+                "page": number,
+                "pages": total,
+                "per_page": per_page,
+                "first": url(1),
+                "last": url(total),
+                "prev": if number > 1 { url(number - 1) } else { serde_json::Value::Null },
+                "next": if number < total { url(number + 1) } else { serde_json::Value::Null },
+            });
+            let chunk = chunks.get(number - 1).map(|chunk| chunk.to_vec());
+
+            (
+                path(number),
+                serde_json::Value::Array(chunk.unwrap_or_default()),
+                Some(paginator),
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn paths(chunks: &[(PathBuf, serde_json::Value, Option<serde_json::Value>)]) -> Vec<PathBuf> {
+        chunks.iter().map(|(path, _, _)| path.clone()).collect()
+    }
+
+    #[test]
+    fn pages_are_split_into_chunks() {
+        let pages = json!([1, 2, 3, 4, 5]);
+        let chunks = paginate(&pages, 2, Path::new("talks/index.html"), false);
+
+        assert_eq!(
+            paths(&chunks),
+            vec![
+                PathBuf::from("talks/index.html"),
+                PathBuf::from("talks/page/2/index.html"),
+                PathBuf::from("talks/page/3/index.html"),
+            ]
+        );
+        assert_eq!(chunks[0].1, json!([1, 2]));
+        assert_eq!(chunks[2].1, json!([5]));
+
+        let paginator = chunks[1].2.clone().unwrap();
+        assert_eq!(paginator["page"], 2);
+        assert_eq!(paginator["pages"], 3);
+        assert_eq!(paginator["first"], "/talks/index.html");
+        assert_eq!(paginator["prev"], "/talks/index.html");
+        assert_eq!(paginator["next"], "/talks/page/3/index.html");
+        assert_eq!(paginator["last"], "/talks/page/3/index.html");
+    }
+
+    #[test]
+    fn paginator_links_at_the_ends() {
+        let chunks = paginate(&json!([1, 2, 3]), 3, Path::new("index.html"), true);
+        assert_eq!(chunks.len(), 1);
+
+        let paginator = chunks[0].2.clone().unwrap();
+        assert_eq!(paginator["first"], "/");
+        assert_eq!(paginator["prev"], serde_json::Value::Null);
+        assert_eq!(paginator["next"], serde_json::Value::Null);
+    }
+
+    #[test]
+    fn empty_sections_have_one_page() {
+        let chunks = paginate(&json!([]), 10, Path::new("talks/index.html"), true);
+        assert_eq!(paths(&chunks), vec![PathBuf::from("talks/index.html")]);
+        assert_eq!(chunks[0].1, json!([]));
+        assert_eq!(chunks[0].2.clone().unwrap()["pages"], 1);
+    }
+
+    #[test]
+    fn pretty_paginator_urls() {
+        let chunks = paginate(&json!([1, 2]), 1, Path::new("talks/index.html"), true);
+        let paginator = chunks[0].2.clone().unwrap();
+        assert_eq!(paginator["first"], "/talks/");
+        assert_eq!(paginator["next"], "/talks/page/2/");
+    }
+}
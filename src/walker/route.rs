@@ -1,19 +1,24 @@
+use std::path::PathBuf;
+
 use anyhow::Result;
 
+use super::{deps::Inputs, feed::FeedFormat};
 use crate::{
     assert_toml_kind,
-    theme::{TemplateRegistry, BASE_NAME, MAIN_KIND},
+    config::THEME_TABLE_KEY,
+    theme::{TemplateRegistry, BASE_NAME, MAIN_KIND, THEME_EXTENDS_KEY},
     util,
 };
 
 const THEME_PATH_KEY: &str = "path";
 const THEME_NAME_KEY: &str = "name";
-const THEME_TABLE_KEY: &str = "theme";
 const PARTIAL_KEY: &str = "kind";
+const ASSETS_TABLE_KEY: &str = "assets";
 
 #[derive(Clone, Debug)]
 pub struct Route {
     pub config: RouteConfig,
+    pub source: PathBuf, // the directory or markdown file, relative to the source
     pub details: RouteDetails,
 }
 
@@ -21,6 +26,7 @@ pub struct Route {
 #[derive(Clone, Debug)]
 pub struct RouteConfig {
     pub theme: ThemeConfig,
+    pub assets: toml::Table, // rules for copying static assets, see walker::assets
 
     pub rest: toml::Table,
 }
@@ -41,6 +47,7 @@ impl Default for RouteConfig {
                 kind: MAIN_KIND.to_string(),
                 rest: toml::Table::new(),
             },
+            assets: toml::Table::new(),
             rest: toml::Table::new(),
         }
     }
@@ -55,11 +62,21 @@ pub enum RouteDetails {
 #[derive(Clone, Debug)]
 pub struct DirectoryRoute {
     pub children: Vec<Route>,
+    pub path: PathBuf, // the mirrored directory, relative to the destination
+    pub feeds: Vec<FeedFormat>, // the feeds of its section, see walker::feed
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Default)]
 pub struct FileRoute {
-    pub html: String,
+    pub content: String,      // the markdown, without front matter
+    pub html: String,         // the rendered page, filled in by walker::render
+    pub content_html: String, // the rendered content alone, without the theme
+    pub path: PathBuf,        // where the html is written to, relative to the destination
+    pub inputs: Inputs,       // everything the page was produced from
+
+    pub later_pages: Vec<(PathBuf, String)>, // the rest of a paginated listing, as (path, html)
+    pub data: serde_json::Map<String, serde_json::Value>, // template data of generated pages
+    pub aliases: Vec<String>,                // urls redirecting to the page, see walker::aliases
 }
 
 // Context for building a route
@@ -69,6 +86,7 @@ pub struct RouteContext {
     pub config: RouteConfig,
 }
 
+#[allow(clippy::needless_arbitrary_self_type)]
 impl RouteContext {
     async fn theme_config_from_toml(self: Self, mut table: toml::Table) -> Result<ThemeConfig> {
         // inherit old partial name if not present, otherwise use the new partial name
@@ -79,16 +97,29 @@ impl RouteContext {
         let name_raw = assert_toml_kind!(String; table, THEME_NAME_KEY)?;
         let theme_path = assert_toml_kind!(String; table, THEME_PATH_KEY)?;
         let kind_raw = assert_toml_kind!(String; table, PARTIAL_KEY)?;
+        let extends = assert_toml_kind!(String; table, THEME_EXTENDS_KEY)?;
 
         table.remove(THEME_NAME_KEY);
         table.remove(THEME_PATH_KEY);
         table.remove(PARTIAL_KEY);
+        table.remove(THEME_EXTENDS_KEY);
 
-        let name = {
+        if extends.is_some() && theme_path.is_none() {
+            anyhow::bail!("A theme can only extend another when it is loaded with `path`.");
+        }
+
+        // a newly loaded theme starts from its own defaults
+        let (name, defaults) = {
             if let Some(path) = theme_path {
-                self.registry.load_template(name_raw, path).await?
+                let name = self
+                    .registry
+                    .clone()
+                    .load_template(name_raw, path, extends)
+                    .await?;
+                let defaults = self.registry.theme_defaults(&name).await?;
+                (name, defaults)
             } else {
-                self.config.theme.name
+                (self.config.theme.name, toml::Table::new())
             }
         };
 
@@ -100,7 +131,8 @@ impl RouteContext {
             }
         };
 
-        let rest = util::toml::merge(self.config.theme.rest, table)?;
+        let rest = util::toml::merge(defaults, self.config.theme.rest)?;
+        let rest = util::toml::merge(rest, table)?;
 
         Ok(ThemeConfig { name, kind, rest })
     }
@@ -115,9 +147,20 @@ impl RouteContext {
 
         table.remove(THEME_TABLE_KEY);
 
+        // the asset rules are build settings rather than page data, so they are kept apart
+        let assets_table =
+            assert_toml_kind!(Table; table, ASSETS_TABLE_KEY)?.unwrap_or(toml::Table::new());
+        table.remove(ASSETS_TABLE_KEY);
+
+        let assets = util::toml::merge(self.config.assets, assets_table)?;
+
         let rest = util::toml::merge(self.config.rest, table)?;
 
-        Ok(RouteConfig { theme, rest })
+        Ok(RouteConfig {
+            theme,
+            assets,
+            rest,
+        })
     }
 
     pub async fn merge_toml(self: Self, table: toml::Table) -> Result<Self> {
@@ -126,11 +169,4 @@ impl RouteContext {
             config: self.route_config_from_toml(table).await?,
         })
     }
-
-    pub async fn file_route_from_content(self: &Self, content: String) -> Result<FileRoute> {
-        let RouteContext { registry, config } = self;
-
-        let html = registry.clone().render_template(&content, &config).await?;
-        Ok(FileRoute { html })
-    }
 }
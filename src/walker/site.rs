@@ -0,0 +1,255 @@
+// Navigation data for the templates: the route tree reduced to the titles and urls
+// of its pages, so that themes can render menus and breadcrumbs.
+//
+// Every directory becomes { title, url, source, children }, where url is the url of
+// its index page (if any), and the children are its other pages and subdirectories.
+// Every page becomes { title, url, source }.
+//
+// The index page of a directory also gets the pages of its section: the other pages
+// of the directory and the index pages of its subdirectories, each along with its
+// config, see `pages`.
+
+use std::{
+    cmp::Ordering,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+use serde_json::{json, Value};
+
+use super::route::{Route, RouteDetails};
+use crate::{assert_toml_kind, config::PRETTY_URLS_KEY, util};
+
+// keys of the config of a page
+pub const TITLE_KEY: &str = "title";
+pub const DATE_KEY: &str = "date";
+pub const INDEX_STEM: &str = "index";
+pub const INDEX_FILE: &str = "index.html";
+pub const PERMALINK_KEY: &str = "permalink";
+
+// how listed pages are ordered
+const SORT_BY_KEY: &str = "sort_by";
+const SORT_REVERSE_KEY: &str = "sort_reverse";
+
+// The navigation tree rooted at this route
+pub fn tree(route: &Route) -> Value {
+    match &route.details {
+        RouteDetails::File(_) => page(route),
+        RouteDetails::Dir(dir) => {
+            let index = dir.children.iter().find(|child| is_index(child));
+
+            let children = dir
+                .children
+                .iter()
+                .filter(|child| !is_index(child))
+                .map(tree)
+                .collect::<Vec<_>>();
+
+            json!({
+                "title": index.map_or_else(|| title(route), title),
+                "url": index.map(route_url),
+                "source": util::paths::path_name(&route.source),
+                "children": children,
+            })
+        }
+    }
+}
+
+// The navigation entry of a single page
+pub fn page(route: &Route) -> Value {
+    json!({
+        "title": title(route),
+        "url": route_url(route),
+        "source": util::paths::path_name(&route.source),
+    })
+}
+
+// The pages of the section whose directory has these children, in source order
+pub fn pages(children: &[Route]) -> Vec<Value> {
+    section(children).map(entry).collect()
+}
+
+// The routes of those pages: the other pages of the directory, and the index pages
+// of its subdirectories
+pub fn section(children: &[Route]) -> impl Iterator<Item = &Route> {
+    children.iter().filter_map(|child| match &child.details {
+        RouteDetails::File(_) if !is_index(child) => Some(child),
+        RouteDetails::File(_) => None,
+        RouteDetails::Dir(dir) => dir.children.iter().find(|child_| is_index(child_)),
+    })
+}
+
+// The navigation entry of a page along with its config, as listed in a section
+pub fn entry(route: &Route) -> Value {
+    let mut entry = page(route);
+    entry["config"] = Value::Object(util::toml::table_to_json(&route.config.rest));
+    entry
+}
+
+// Sort listed pages by the config key the listing page asks for (if any). Pages
+// missing the key come last, in their previous order.
+pub fn sort(pages: &mut [Value], config: &toml::Table) -> Result<()> {
+    let Some(key) = assert_toml_kind!(String; config, SORT_BY_KEY)? else {
+        return Ok(());
+    };
+    let reverse = assert_toml_kind!(Boolean; config, SORT_REVERSE_KEY)?.unwrap_or(false);
+
+    pages.sort_by(
+        |a, b| match (a["config"].get(&key), b["config"].get(&key)) {
+            (Some(a_), Some(b_)) if reverse => compare(b_, a_),
+            (Some(a_), Some(b_)) => compare(a_, b_),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        },
+    );
+
+    Ok(())
+}
+
+// Numbers compare as numbers, and dates (strings that util::date reads) by the
+// time they stand for, whatever their offset or format, as in the feeds. Everything
+// else compares by its text.
+pub fn compare(a: &Value, b: &Value) -> Ordering {
+    match (a, b) {
+        (Value::Number(a_), Value::Number(b_)) => a_
+            .as_f64()
+            .partial_cmp(&b_.as_f64())
+            .unwrap_or(Ordering::Equal),
+        (Value::String(a_), Value::String(b_)) => {
+            match (util::date::parse(a_), util::date::parse(b_)) {
+                (Ok(a_date), Ok(b_date)) => a_date.cmp(&b_date),
+                _ => a_.cmp(b_),
+            }
+        }
+        _ => a.to_string().cmp(&b.to_string()),
+    }
+}
+
+// The url a path relative to the destination is served at. With pretty urls, an
+// index.html is served at the url of its directory.
+pub fn url(path: &Path, pretty: bool) -> String {
+    let name = util::paths::path_name(path);
+    match name.strip_suffix(INDEX_FILE) {
+        Some(dir) if pretty && (dir.is_empty() || dir.ends_with('/')) => format!("/{}", dir),
+        _ => format!("/{}", name),
+    }
+}
+
+// The url of the page (or directory) of a route
+pub fn route_url(route: &Route) -> String {
+    url(output(route), is_pretty(&route.config.rest))
+}
+
+// Whether pages are written to <stem>/index.html rather than <stem>.html, and
+// linked to by the url of that directory. A permalink ending in / asks for the
+// same, see walker::permalink. The type of the key is checked by the walk.
+pub fn is_pretty(config: &toml::Table) -> bool {
+    let permalink = config.get(PERMALINK_KEY).and_then(toml::Value::as_str);
+
+    matches!(
+        config.get(PRETTY_URLS_KEY),
+        Some(toml::Value::Boolean(true))
+    ) || permalink.is_some_and(|permalink_| permalink_.ends_with('/'))
+}
+
+// Whether the route is the index page of its directory
+pub fn is_index(route: &Route) -> bool {
+    matches!(route.details, RouteDetails::File(_))
+        && route
+            .source
+            .file_stem()
+            .is_some_and(|stem| stem == INDEX_STEM)
+}
+
+// The configured title, or else the file or directory name. An index page is
+// named after its directory.
+pub fn title(route: &Route) -> String {
+    if let Some(toml::Value::String(title)) = route.config.rest.get(TITLE_KEY) {
+        return title.clone();
+    }
+
+    let name = match &route.details {
+        RouteDetails::Dir(_) => route.source.file_name(),
+        RouteDetails::File(_) if is_index(route) => route
+            .source
+            .parent()
+            .and_then(Path::file_name)
+            .or(route.source.file_stem()),
+        RouteDetails::File(_) => route.source.file_stem(),
+    };
+
+    name.map(|name_| name_.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+fn output(route: &Route) -> &PathBuf {
+    match &route.details {
+        RouteDetails::Dir(dir) => &dir.path,
+        RouteDetails::File(file) => &file.path,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dates_compare_by_time() {
+        let cmp = |a: &str, b: &str| compare(&json!(a), &json!(b));
+
+        // later as text, but earlier in time
+        assert_eq!(
+            cmp("2024-01-01T00:00:00+05:00", "2023-12-31T23:00:00Z"),
+            Ordering::Less
+        );
+        assert_eq!(
+            cmp("2024-01-01T02:00:00+02:00", "2024-01-01T00:00:00Z"),
+            Ordering::Equal
+        );
+        // a bare date is midnight in local time
+        assert_eq!(cmp("2024-01-02", "2024-01-02 10:00"), Ordering::Less);
+        assert_eq!(cmp("2024-01-02", "2024-01-01T23:59:59"), Ordering::Greater);
+    }
+
+    #[test]
+    fn urls() {
+        let url = |path: &str, pretty| url(Path::new(path), pretty);
+
+        assert_eq!(url("index.html", false), "/index.html");
+        assert_eq!(url("index.html", true), "/");
+        assert_eq!(url("talks/index.html", true), "/talks/");
+        assert_eq!(url("talks/index.html", false), "/talks/index.html");
+        assert_eq!(url("talks/a.html", true), "/talks/a.html");
+        // only a whole file name counts as an index
+        assert_eq!(url("talks/myindex.html", true), "/talks/myindex.html");
+        // the root directory
+        assert_eq!(url("", true), "/");
+    }
+
+    #[test]
+    fn pretty_configs() {
+        let pretty = |text: &str| is_pretty(&toml::from_str(text).unwrap());
+
+        assert!(!pretty(""));
+        assert!(pretty("pretty_urls = true"));
+        assert!(!pretty("pretty_urls = false"));
+        assert!(!pretty(r#"pretty_urls = "yes""#));
+        // a permalink ending in / asks for the same
+        assert!(pretty(r#"permalink = "/talks/:title/""#));
+        assert!(pretty("pretty_urls = false\npermalink = \"/:title/\""));
+        assert!(!pretty(r#"permalink = "/talks/:title.html""#));
+    }
+
+    #[test]
+    fn other_values() {
+        let cmp = |a: Value, b: Value| compare(&a, &b);
+
+        assert_eq!(cmp(json!(9), json!(10)), Ordering::Less);
+        assert_eq!(cmp(json!(2.5), json!(2)), Ordering::Greater);
+        assert_eq!(cmp(json!("b"), json!("a")), Ordering::Greater);
+        // only one side is a date
+        assert_eq!(cmp(json!("2024-01-02"), json!("soon")), Ordering::Less);
+        assert_eq!(cmp(json!(true), json!(false)), Ordering::Greater);
+    }
+}
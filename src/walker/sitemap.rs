@@ -0,0 +1,124 @@
+// sitemap.xml lists the absolute url of every rendered page, for search engines.
+// A page can leave itself (or a __common.toml a whole directory) out with
+// `sitemap = false`. The last modification of a page is its `lastmod` key, or else
+// the modification time of its markdown file.
+//
+// With --robots, a robots.txt pointing at the sitemap is written too.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use async_recursion::async_recursion;
+use chrono::{DateTime, FixedOffset, SecondsFormat};
+
+use super::{
+    route::{Route, RouteDetails},
+    site,
+};
+use crate::{assert_toml_kind, util, util::xml::escape};
+
+pub const SITEMAP_FILE: &str = "sitemap.xml";
+pub const ROBOTS_FILE: &str = "robots.txt";
+
+const SITEMAP_KEY: &str = "sitemap";
+const LASTMOD_KEY: &str = "lastmod";
+
+struct Entry {
+    url: String,
+    lastmod: Option<DateTime<FixedOffset>>,
+}
+
+// Write the sitemap of the route tree (and robots.txt) into the destination
+pub async fn write(
+    route: &Route,
+    source: &PathBuf,
+    destination: &Path,
+    base_url: &str,
+    robots: bool,
+) -> Result<()> {
+    let base_url = base_url.trim_end_matches('/');
+
+    let mut entries = vec![];
+    collect(route, source, base_url, &mut entries).await?;
+
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+    xml.push_str("<urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n");
+    for entry in entries {
+        xml.push_str("  <url>\n");
+        xml.push_str(&format!("    <loc>{}</loc>\n", escape(&entry.url)));
+        if let Some(lastmod) = entry.lastmod {
+            xml.push_str(&format!(
+                "    <lastmod>{}</lastmod>\n",
+                lastmod.to_rfc3339_opts(SecondsFormat::Secs, true)
+            ));
+        }
+        xml.push_str("  </url>\n");
+    }
+    xml.push_str("</urlset>\n");
+
+    util::paths::write_if_changed(&destination.join(SITEMAP_FILE), &xml).await?;
+
+    if robots {
+        let contents = format!(
+            "User-agent: *\nAllow: /\n\nSitemap: {}/{}\n",
+            base_url, SITEMAP_FILE
+        );
+        util::paths::write_if_changed(&destination.join(ROBOTS_FILE), &contents).await?;
+    }
+
+    Ok(())
+}
+
+#[async_recursion]
+async fn collect(
+    route: &Route,
+    source: &PathBuf,
+    base_url: &str,
+    entries: &mut Vec<Entry>,
+) -> Result<()> {
+    match &route.details {
+        RouteDetails::Dir(dir) => {
+            for child in dir.children.iter() {
+                collect(child, source, base_url, entries).await?;
+            }
+        }
+        RouteDetails::File(file) => {
+            let listed = assert_toml_kind!(Boolean; route.config.rest, SITEMAP_KEY)?;
+            if !listed.unwrap_or(true) {
+                return Ok(());
+            }
+
+            let lastmod = lastmod(route, source).await?;
+            let pretty = site::is_pretty(&route.config.rest);
+
+            // every page of a paginated listing is listed
+            let paths =
+                std::iter::once(&file.path).chain(file.later_pages.iter().map(|(path, _)| path));
+            for path in paths {
+                entries.push(Entry {
+                    url: format!("{}{}", base_url, site::url(path, pretty)),
+                    lastmod,
+                });
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn lastmod(route: &Route, source: &Path) -> Result<Option<DateTime<FixedOffset>>> {
+    if let Some(value) = route.config.rest.get(LASTMOD_KEY) {
+        let date =
+            util::date::from_toml(value).context(format!("In `{}`.", route.source.display()))?;
+        return Ok(Some(date));
+    }
+
+    // generated pages have no file
+    let modified = tokio::fs::metadata(source.join(&route.source))
+        .await
+        .and_then(|metadata| metadata.modified());
+
+    Ok(modified
+        .ok()
+        .map(|time| DateTime::<chrono::Local>::from(time).fixed_offset()))
+}
@@ -0,0 +1,253 @@
+// Taxonomies group pages by the terms they declare under a taxonomy key, such as
+// `tags = ["probability", "analysis"]`. They are opt-in: only the keys listed in
+// `taxonomies = ["tags"]` at the root are taxonomies, so that sites are free to
+// use a key like tags for data of their own. Every taxonomy gets an index page at
+// <taxonomy>/index.html, and every term a page at <taxonomy>/<term>/index.html
+// listing the pages with that term, rendered with the taxonomy and term kinds.
+//
+// Arrays are extended when configs are merged, so a term declared in a
+// __common.toml is inherited by every page below it. A page that repeats an
+// inherited term (in any case) still counts once.
+
+use std::{collections::BTreeMap, path::PathBuf};
+
+use anyhow::Result;
+use serde_json::{json, Value};
+
+use super::{
+    route::{Route, RouteDetails},
+    site,
+};
+use crate::{assert_toml_kind, util};
+
+pub const TAXONOMIES_KEY: &str = "taxonomies"; // the taxonomy keys, set for the root
+
+#[derive(Debug)]
+pub struct Taxonomy {
+    pub name: String, // the config key
+    pub terms: Vec<Term>,
+    pub pretty: bool, // whether its urls leave out index.html, see site::url
+}
+
+#[derive(Debug)]
+pub struct Term {
+    pub name: String, // as first declared
+    pub slug: String,
+    pub pages: Vec<Value>, // listing entries of the pages, see site::entry
+}
+
+// Gather the terms of every markdown page below these routes
+pub fn collect(routes: &[Route], names: &[String], pretty: bool) -> Result<Vec<Taxonomy>> {
+    let mut taxonomies = vec![];
+
+    for name in names {
+        let mut terms = BTreeMap::new(); // slug -> term
+        for route in routes {
+            add_terms(route, name, &mut terms)?;
+        }
+
+        taxonomies.push(Taxonomy {
+            name: name.clone(),
+            terms: terms.into_values().collect(),
+            pretty,
+        });
+    }
+
+    Ok(taxonomies)
+}
+
+fn add_terms(route: &Route, name: &str, terms: &mut BTreeMap<String, Term>) -> Result<()> {
+    match &route.details {
+        RouteDetails::Dir(dir) => {
+            for child in dir.children.iter() {
+                add_terms(child, name, terms)?;
+            }
+        }
+        // generated pages have no source file, and are never tagged
+        RouteDetails::File(_) if util::paths::ext_is(&route.source, "md") => {
+            let declared = assert_toml_kind!(Array; route.config.rest, name)?.unwrap_or_default();
+
+            let mut seen = vec![];
+            for value in declared {
+                let toml::Value::String(term_name) = value else {
+                    anyhow::bail!("Key `{}` must be an array of strings!", name);
+                };
+
+                let slug = util::slug::slugify(&term_name);
+                if slug.is_empty() || seen.contains(&slug) {
+                    continue;
+                }
+                seen.push(slug.clone());
+
+                terms
+                    .entry(slug.clone())
+                    .or_insert_with(|| Term {
+                        name: term_name,
+                        slug,
+                        pages: vec![],
+                    })
+                    .pages
+                    .push(site::entry(route));
+            }
+        }
+        RouteDetails::File(_) => {}
+    }
+
+    Ok(())
+}
+
+#[allow(clippy::needless_arbitrary_self_type)]
+impl Taxonomy {
+    pub fn slug(self: &Self) -> String {
+        util::slug::slugify(&self.name)
+    }
+
+    // Template data of the taxonomy index page
+    pub fn data(self: &Self) -> Value {
+        let terms = self
+            .terms
+            .iter()
+            .map(|term| {
+                json!({
+                    "name": term.name,
+                    "slug": term.slug,
+                    "url": self.url(Some(term)),
+                    "count": term.pages.len(),
+                })
+            })
+            .collect::<Vec<_>>();
+
+        json!({
+            "name": self.name,
+            "url": self.url(None),
+            "terms": terms,
+        })
+    }
+
+    // Template data of the page of one of its terms
+    pub fn term_data(self: &Self, term: &Term) -> Value {
+        json!({
+            "name": term.name,
+            "slug": term.slug,
+            "url": self.url(Some(term)),
+            "taxonomy": self.name,
+            "taxonomy_url": self.url(None),
+        })
+    }
+
+    fn url(self: &Self, term: Option<&Term>) -> String {
+        let mut path = PathBuf::from(self.slug());
+        if let Some(term_) = term {
+            path.push(&term_.slug);
+        }
+        path.push(site::INDEX_FILE);
+
+        site::url(&path, self.pretty)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::walker::route::{DirectoryRoute, FileRoute, RouteConfig};
+
+    fn page(source: &str, config: &str) -> Route {
+        Route {
+            config: RouteConfig {
+                rest: toml::from_str(config).unwrap(),
+                ..RouteConfig::default()
+            },
+            source: PathBuf::from(source),
+            details: RouteDetails::File(FileRoute {
+                path: PathBuf::from(source).with_extension("html"),
+                ..FileRoute::default()
+            }),
+        }
+    }
+
+    fn dir(source: &str, children: Vec<Route>) -> Route {
+        Route {
+            config: RouteConfig::default(),
+            source: PathBuf::from(source),
+            details: RouteDetails::Dir(DirectoryRoute {
+                children,
+                path: PathBuf::from(source),
+                feeds: vec![],
+            }),
+        }
+    }
+
+    fn sources(term: &Term) -> Vec<&str> {
+        term.pages
+            .iter()
+            .map(|page| page["source"].as_str().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn terms_are_deduped_by_slug() {
+        let routes = vec![
+            page("a.md", r#"tags = ["Spectral Theory", "graphs"]"#),
+            dir(
+                "talks",
+                vec![
+                    // an inherited term repeated in another case counts once
+                    page(
+                        "talks/b.md",
+                        r#"tags = ["spectral theory", "Spectral-Theory!"]"#,
+                    ),
+                    page("talks/c.md", r#"tags = ["Graphs", "!!"]"#),
+                ],
+            ),
+            // pages without a source file are never tagged
+            page("talks/page/2/index.html", r#"tags = ["graphs"]"#),
+        ];
+
+        let taxonomies = collect(&routes, &["tags".to_owned()], false).unwrap();
+        assert_eq!(taxonomies.len(), 1);
+
+        let terms = &taxonomies[0].terms;
+        let slugs = terms
+            .iter()
+            .map(|term| term.slug.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(slugs, vec!["graphs", "spectral-theory"]);
+
+        // named as first declared
+        assert_eq!(terms[0].name, "graphs");
+        assert_eq!(terms[1].name, "Spectral Theory");
+        assert_eq!(sources(&terms[0]), vec!["a.md", "talks/c.md"]);
+        assert_eq!(sources(&terms[1]), vec!["a.md", "talks/b.md"]);
+    }
+
+    #[test]
+    fn term_urls() {
+        let routes = vec![page("a.md", r#""Research Areas" = ["Number Theory"]"#)];
+        let names = ["Research Areas".to_owned()];
+
+        let taxonomy = &collect(&routes, &names, false).unwrap()[0];
+        let term = &taxonomy.terms[0];
+        assert_eq!(taxonomy.slug(), "research-areas");
+        assert_eq!(
+            taxonomy.term_data(term)["url"],
+            "/research-areas/number-theory/index.html"
+        );
+        assert_eq!(taxonomy.data()["url"], "/research-areas/index.html");
+        assert_eq!(taxonomy.data()["terms"][0]["count"], 1);
+
+        let taxonomy = &collect(&routes, &names, true).unwrap()[0];
+        assert_eq!(
+            taxonomy.term_data(&taxonomy.terms[0])["url"],
+            "/research-areas/number-theory/"
+        );
+    }
+
+    #[test]
+    fn terms_must_be_strings() {
+        let routes = vec![page("a.md", "tags = [1]")];
+        assert!(collect(&routes, &["tags".to_owned()], false).is_err());
+
+        let routes = vec![page("a.md", r#"tags = "one""#)];
+        assert!(collect(&routes, &["tags".to_owned()], false).is_err());
+    }
+}
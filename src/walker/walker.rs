@@ -1,36 +1,111 @@
-use std::{ffi::OsString, path::PathBuf};
+use std::{
+    ffi::OsString,
+    path::{Component, Path, PathBuf},
+    sync::Arc,
+};
 
 use anyhow::{Context, Result};
 
 use async_recursion::async_recursion;
+use tokio::sync::Mutex;
 use tracing::info;
 
-use super::route::{Route, RouteConfig, RouteContext, RouteDetails};
-
-use crate::{fatal, theme::TemplateRegistry, use_path, util, walker::route::DirectoryRoute};
+use super::{
+    aliases,
+    assets::{self, AssetRules},
+    deps::{DependencyGraph, Inputs},
+    feed::FeedFormat,
+    ignores::{IgnoreRules, IGNORE_FILE},
+    manifest, permalink,
+    render::{render_route, Renderer},
+    route::{FileRoute, Route, RouteConfig, RouteContext, RouteDetails},
+    site, sitemap,
+    taxonomy::{self, TAXONOMIES_KEY},
+};
+
+use crate::{
+    assert_toml_kind,
+    config::{SiteConfig, PRETTY_URLS_KEY},
+    theme::{
+        TemplateRegistry, PAGES_SLOT, SECTION_KIND, TAXONOMY_KIND, TAXONOMY_SLOT, TERM_KIND,
+        TERM_SLOT, THEME_ASSETS_DIR,
+    },
+    util,
+    walker::route::DirectoryRoute,
+};
 
 const COMMON_CONFIG_FILE: &str = "__common.toml";
 
+const DRAFT_KEY: &str = "draft";
+const PUBLISH_DATE_KEY: &str = "publish_date";
+const LISTING_KEY: &str = "listing"; // generate an index page for directories without one
+
+// pseudo-input recorded for pages that carry the live reload snippet
+const LIVE_RELOAD_INPUT: &str = "__live_reload__";
+// and for every page, since every page depends on the site config
+const SITE_CONFIG_INPUT: &str = "__site_config__";
+
+#[derive(Clone, Debug, Default)]
+pub struct BuildOptions {
+    pub force: bool,       // delete folders if necessary
+    pub keep: bool,        // write into the existing destination instead of replacing it
+    pub prune: bool,       // with keep, delete outputs of the last build that are gone now
+    pub incremental: bool, // keep the destination, and only rerender pages whose inputs changed
+    pub drafts: bool,      // also build pages marked as drafts
+    pub future: bool,      // also build pages with a publish date in the future
+
+    pub base_url: Option<String>, // where the site is served from, for absolute urls
+    pub robots: bool,             // also write a robots.txt pointing at the sitemap
+}
+
+#[allow(clippy::needless_arbitrary_self_type)]
+impl BuildOptions {
+    pub fn keeps_destination(self: &Self) -> bool {
+        self.keep || self.incremental
+    }
+}
+
+// State shared by every task of a single build
+#[derive(Debug, Default)]
+pub(super) struct BuildState {
+    pub source: PathBuf,      // root source directory
+    pub destination: PathBuf, // root destination directory
+
+    pub previous: DependencyGraph, // graph recorded by the last build
+    pub current: Mutex<DependencyGraph>, // graph being recorded by this build
+}
+
 #[derive(Clone, Debug)]
 pub struct Walker {
     source: PathBuf,      // source directory
     destination: PathBuf, // destination directory
-    force: bool,          // delete folders if necessary
+    options: BuildOptions,
+    site: Arc<SiteConfig>, // site-wide settings, see config.rs
+
+    inputs: Inputs,       // inputs shared by everything below this directory
+    ignores: IgnoreRules, // rules for skipping paths below this directory
+    state: Arc<BuildState>,
 
     context: RouteContext,
 }
 
+#[allow(clippy::needless_arbitrary_self_type)]
 impl Walker {
     pub fn new(
         source: PathBuf,
         destination: PathBuf,
-        force: bool,
+        options: BuildOptions,
+        site: SiteConfig,
         registry: TemplateRegistry,
     ) -> Self {
         Walker {
             source,
             destination,
-            force,
+            options,
+            site: Arc::new(site),
+            inputs: Inputs::new(),
+            ignores: IgnoreRules::default(),
+            state: Arc::new(BuildState::default()),
             context: RouteContext {
                 registry,
                 config: RouteConfig::default(),
@@ -38,43 +113,270 @@ impl Walker {
         }
     }
 
-    pub async fn walk(self: Self) {
-        util::dir::remove_and_create(&self.destination, self.force).await;
+    pub async fn walk(mut self: Self) -> Result<Route> {
+        let destination = self.destination.clone();
+        let [staging, old, deps] = scratch_paths(&destination)?;
+
+        // Everything is rendered into a staging directory first, which replaces the
+        // destination only once the whole tree is done. So a failed build leaves
+        // the destination untouched.
+        util::dir::remove_if_exists(&staging).await; // left over by an interrupted build
 
-        // Walk the source
-        let routes = process_directory(self).await;
-        if let Err(err) = routes {
-            fatal!("Error: {}", err.to_string());
+        let previous = if self.options.keeps_destination() {
+            // start from what is already there
+            if tokio::fs::try_exists(&destination).await.unwrap_or(false) {
+                util::dir::link_tree(&destination, &staging).await?;
+            }
+            DependencyGraph::load(&deps).await
+        } else {
+            util::dir::ensure_replaceable(&destination, self.options.force).await;
+            DependencyGraph::default()
+        };
+
+        util::dir::create(&staging).await;
+
+        self.destination = staging.clone();
+        self.state = Arc::new(BuildState {
+            source: self.source.clone(),
+            destination: staging.clone(),
+            previous,
+            current: Mutex::new(DependencyGraph::default()),
+        });
+
+        let state = self.state.clone();
+        let options = self.options.clone();
+        let registry = self.context.registry.clone();
+
+        let route = match build(self, registry).await {
+            Ok(route_) => route_,
+            Err(err) => {
+                util::dir::remove_if_exists(&staging).await;
+                return Err(err);
+            }
+        };
+
+        let current = state.current.lock().await;
+
+        if options.keeps_destination() && options.prune {
+            prune(&staging, &state.previous, &current).await?;
         }
 
-        dbg!(routes.unwrap());
+        manifest::write(&route, &staging).await?;
+
+        util::dir::swap_in(&staging, &destination, &old).await?;
+
+        // only once the destination holds what the graph describes
+        current.save(&deps).await?;
+
+        Ok(route)
+    }
+
+    // Record an input of everything below this directory
+    fn add_input(self: &mut Self, path: &Path, contents: &str) {
+        let name = relative_name(&self.state.source, path);
+        self.inputs
+            .insert(name, util::hash::fingerprint(contents.as_bytes()));
+    }
+
+    // The theme a page is rendered with is an input too
+    async fn add_theme_inputs(self: &mut Self, context: &RouteContext) -> Result<()> {
+        let theme_sources = context
+            .registry
+            .theme_sources(&context.config.theme.name)
+            .await?;
+        for (resource, data) in theme_sources {
+            self.inputs
+                .insert(resource, util::hash::fingerprint(data.as_bytes()));
+        }
+        if context.registry.has_live_reload() {
+            self.inputs
+                .insert(LIVE_RELOAD_INPUT.to_owned(), String::new());
+        }
+        Ok(())
     }
 }
 
+// Walk the source to collect the route tree, and then render its pages
+async fn build(mut walker: Walker, registry: TemplateRegistry) -> Result<Route> {
+    let state = walker.state.clone();
+    let options = walker.options.clone();
+    let site = walker.site.clone();
+
+    // the root starts from the default theme of the site, and depends on its settings
+    walker.context = walker.context.clone().merge_toml(site.root_table()).await?;
+    walker
+        .inputs
+        .insert(SITE_CONFIG_INPUT.to_owned(), site.fingerprint.clone());
+
+    // the root directory always produces a route
+    let route = process_directory(walker).await?.unwrap();
+
+    let theme_assets = registry.theme_assets().await;
+
+    let renderer = Renderer::new(registry, options.clone(), state.clone(), &site, &route);
+    let route = render_route(route, renderer).await?;
+
+    // after everything else, so that the pages and assets of the site win
+    for assets in theme_assets {
+        let destination = state.destination.join(THEME_ASSETS_DIR);
+        process_theme_assets(assets, destination, &state, &options).await?;
+    }
+
+    match &options.base_url {
+        Some(base_url) => {
+            sitemap::write(
+                &route,
+                &state.source,
+                &state.destination,
+                base_url,
+                options.robots,
+            )
+            .await?
+        }
+        None => info!("Skipping the sitemap, since no base url is set."),
+    }
+
+    if site.redirects {
+        aliases::write_redirects_file(&route, &state.destination).await?;
+    }
+
+    Ok(route)
+}
+
+// Drafts and pages with a publish date in the future are left out, unless asked for
+fn is_published(config: &RouteConfig, options: &BuildOptions) -> Result<bool> {
+    let draft = assert_toml_kind!(Boolean; config.rest, DRAFT_KEY)?.unwrap_or(false);
+    if draft && !options.drafts {
+        return Ok(false);
+    }
+
+    if let Some(publish_date) = config.rest.get(PUBLISH_DATE_KEY) {
+        let publish_date = util::date::from_toml(publish_date)
+            .context(format!("Invalid `{}`.", PUBLISH_DATE_KEY))?;
+        if publish_date > chrono::Local::now() && !options.future {
+            return Ok(false);
+        }
+    }
+
+    Ok(true)
+}
+
+// The siblings of destination used while building: the staging directory the new
+// tree is rendered into, the place the old tree is moved aside to, and the file
+// the dependency graph is kept in
+pub fn scratch_paths(destination: &Path) -> Result<[PathBuf; 3]> {
+    // the siblings of `.` or `..` would end up inside of it
+    if !matches!(
+        destination.components().next_back(),
+        Some(Component::Normal(_))
+    ) {
+        anyhow::bail!(
+            "Destination `{}` has to end in the name of a directory!",
+            destination.display()
+        );
+    }
+
+    let resolved = util::paths::resolve(destination)?;
+    let Some(name) = resolved.file_name() else {
+        anyhow::bail!("Destination `{}` has no name!", destination.display());
+    };
+    let name = name.to_string_lossy();
+
+    let paths = [
+        resolved.with_file_name(format!(".{}.staging", name)),
+        resolved.with_file_name(format!(".{}.old", name)),
+        resolved.with_file_name(format!(".{}.deps.toml", name)),
+    ];
+
+    // the destination would be linked into its own staging directory, without end
+    if paths.iter().any(|path| path.starts_with(&resolved)) {
+        anyhow::bail!(
+            "Destination `{}` contains its own staging directory!",
+            destination.display()
+        );
+    }
+
+    Ok(paths)
+}
+
+// Delete the outputs recorded by the last build that this build did not produce.
+// Files that were never produced by ferne are not tracked, so they are left alone.
+async fn prune(
+    destination: &PathBuf,
+    previous: &DependencyGraph,
+    current: &DependencyGraph,
+) -> Result<()> {
+    for output in previous.outputs.keys() {
+        if current.outputs.contains_key(output) {
+            continue;
+        }
+
+        // never touch anything outside the destination, whatever the graph says
+        let escapes = Path::new(output)
+            .components()
+            .any(|component| !matches!(component, Component::Normal(_)));
+        if escapes {
+            continue;
+        }
+
+        let path = destination.join(output);
+        if !tokio::fs::try_exists(&path).await.unwrap_or(false) {
+            continue;
+        }
+
+        info!("Pruning stale output `{}`", path.display());
+        tokio::fs::remove_file(&path)
+            .await
+            .context(format!("Failed to delete file `{}`.", path.display()))?;
+
+        // also remove the directories this leaves empty
+        for dir in path.ancestors().skip(1) {
+            if dir == destination || tokio::fs::remove_dir(dir).await.is_err() {
+                break;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// Name of a path relative to a root, with forward slashes on every platform
+pub(super) fn relative_name(root: &Path, path: &Path) -> String {
+    let relative = path.strip_prefix(root).unwrap_or(path);
+    relative
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
 // Uses option to match the type of process_file below
 #[async_recursion]
 async fn process_directory(mut walker: Walker) -> Result<Option<Route>> {
-    let common_toml = use_path!(walker.source, COMMON_CONFIG_FILE; path => {
-        info!(
-            "Found common configuration file: `{}`",
-            path.display()
-        );
-        util::toml::read(&path).await
-    })?;
+    let common_path = walker.source.join(COMMON_CONFIG_FILE);
+    info!(
+        "Found common configuration file: `{}`",
+        common_path.display()
+    );
+    let (mut common_toml, common_contents) = util::toml::read(&common_path).await?;
+    walker.add_input(&common_path, &common_contents);
+
+    walker.ignores = walker
+        .ignores
+        .extend(&walker.source, &mut common_toml)
+        .await?;
+    let feeds = FeedFormat::from_toml(&mut common_toml)?;
 
     // update context with common toml
     let context = walker.context.clone().merge_toml(common_toml).await?;
     walker.context = context;
 
-    let Walker {
-        source,
-        destination,
-        force,
-        ..
-    } = &walker;
-
     // Create destination directory
-    util::dir::remove_and_create(destination, *force).await;
+    util::dir::create(&walker.destination).await;
+
+    let asset_rules = AssetRules::from_toml(&walker.context.config.assets)?;
+
+    let Walker { source, .. } = &walker;
 
     let mut entries = tokio::fs::read_dir(&source)
         .await
@@ -106,8 +408,19 @@ async fn process_directory(mut walker: Walker) -> Result<Option<Route>> {
 
         let name = entry.file_name();
 
+        if name == IGNORE_FILE || walker.ignores.is_ignored(&path, ft.is_dir()) {
+            info!("Ignoring `{}`", disp);
+            continue;
+        }
+
         let mut config = walker.clone();
 
+        // themes are read by the registry, and are no part of the content
+        if ft.is_dir() && walker.context.registry.is_theme_dir(&path).await {
+            info!("Skipping theme directory `{}`", disp);
+            continue;
+        }
+
         // spawn tasks for child routes
         if ft.is_dir() {
             // for directories, the config source/destination is updated to include the
@@ -126,6 +439,9 @@ async fn process_directory(mut walker: Walker) -> Result<Option<Route>> {
             // as a separate argument
             info!("Reading file `{}`", disp);
             children_tasks.spawn(process_file(config, name));
+        } else if ft.is_file() && asset_rules.matches(&relative_name(&walker.state.source, &path)) {
+            info!("Copying asset `{}`", disp);
+            children_tasks.spawn(process_asset(config, name, asset_rules.link));
         };
     }
 
@@ -148,52 +464,433 @@ async fn process_directory(mut walker: Walker) -> Result<Option<Route>> {
         }
     }
 
-    let route_details = RouteDetails::Dir(DirectoryRoute { children });
+    // a directory that produced nothing at all, say one holding only themes, is left
+    // out rather than mirrored as an empty directory
+    let is_root = walker.source == walker.state.source;
+    if children.is_empty() && !is_root && tokio::fs::remove_dir(&walker.destination).await.is_ok() {
+        return Ok(None);
+    }
 
-    Ok(Some(Route {
-        config: walker.context.config,
+    // the children finish in any order, keep them in a stable one
+    children.sort_by(|a, b| a.source.cmp(&b.source));
+
+    let listing = assert_toml_kind!(Boolean; walker.context.config.rest, LISTING_KEY)?;
+    if listing.unwrap_or(false) && !children.iter().any(site::is_index) {
+        let route = generated_route(walker.clone(), SECTION_KIND, serde_json::Map::new()).await?;
+        children.push(route);
+    }
+
+    // the whole tree is known at the root, so the taxonomies are generated there
+    if is_root {
+        let routes = taxonomy_routes(walker.clone(), &children).await?;
+        children.extend(routes);
+    }
+
+    let mut route = directory_route(&walker, children);
+    if let RouteDetails::Dir(dir) = &mut route.details {
+        dir.feeds = feeds;
+    }
+
+    Ok(Some(route))
+}
+
+// A page generated rather than read from the source, rendered with the given kind
+// of the theme into the index.html of the walker's directory. Its source is the
+// (nonexistent) index of that directory.
+async fn generated_route(
+    mut walker: Walker,
+    kind: &str,
+    data: serde_json::Map<String, serde_json::Value>,
+) -> Result<Route> {
+    let mut context = walker.context.clone();
+    context.config.theme.kind = kind.to_owned();
+
+    walker.add_theme_inputs(&context).await?;
+
+    let source = walker.source.join(site::INDEX_STEM);
+    let path = walker
+        .destination
+        .join(format!("{}.html", site::INDEX_STEM));
+    let route = FileRoute {
+        content: String::new(),
+        html: String::new(),
+        content_html: String::new(),
+        path: PathBuf::from(relative_name(&walker.state.destination, &path)),
+        inputs: walker.inputs,
+        later_pages: vec![],
+        data,
+        aliases: vec![],
+    };
+
+    Ok(Route {
+        source: PathBuf::from(relative_name(&walker.state.source, &source)),
+        config: context.config,
+        details: RouteDetails::File(route),
+    })
+}
+
+// The pages of every taxonomy declared below the root, as a directory per taxonomy
+async fn taxonomy_routes(walker: Walker, children: &[Route]) -> Result<Vec<Route>> {
+    let names = match assert_toml_kind!(Array; walker.context.config.rest, TAXONOMIES_KEY)? {
+        Some(names) => names
+            .into_iter()
+            .map(|name| match name {
+                toml::Value::String(name_) => Ok(name_),
+                _ => anyhow::bail!("Key `{}` must be an array of strings!", TAXONOMIES_KEY),
+            })
+            .collect::<Result<Vec<_>>>()?,
+        None => vec![], // taxonomies are opt-in
+    };
+
+    let mut routes = vec![];
+
+    let pretty = site::is_pretty(&walker.context.config.rest);
+
+    for taxonomy in taxonomy::collect(children, &names, pretty)? {
+        if taxonomy.terms.is_empty() {
+            continue;
+        }
+
+        let mut taxonomy_walker = walker.clone();
+        taxonomy_walker.source.push(taxonomy.slug());
+        taxonomy_walker.destination.push(taxonomy.slug());
+
+        let source = PathBuf::from(relative_name(&walker.state.source, &taxonomy_walker.source));
+        if children.iter().any(|child| child.source == source) {
+            anyhow::bail!(
+                "Pages for the taxonomy `{}` are generated at `{}`, which already exists!",
+                taxonomy.name,
+                source.display()
+            );
+        }
+
+        let mut data = serde_json::Map::new();
+        data.insert(TAXONOMY_SLOT.to_owned(), taxonomy.data());
+
+        let mut taxonomy_children =
+            vec![generated_route(taxonomy_walker.clone(), TAXONOMY_KIND, data).await?];
+
+        for term in taxonomy.terms.iter() {
+            let mut term_walker = taxonomy_walker.clone();
+            term_walker.source.push(&term.slug);
+            term_walker.destination.push(&term.slug);
+
+            let mut pages = term.pages.clone();
+            site::sort(&mut pages, &walker.context.config.rest)?;
+
+            let mut data = serde_json::Map::new();
+            data.insert(TERM_SLOT.to_owned(), taxonomy.term_data(term));
+            data.insert(PAGES_SLOT.to_owned(), serde_json::Value::Array(pages));
+
+            let route = generated_route(term_walker.clone(), TERM_KIND, data).await?;
+            taxonomy_children.push(directory_route(&term_walker, vec![route]));
+        }
+
+        routes.push(directory_route(&taxonomy_walker, taxonomy_children));
+    }
+
+    Ok(routes)
+}
+
+// The route of the walker's directory, with these children
+fn directory_route(walker: &Walker, children: Vec<Route>) -> Route {
+    let route_details = RouteDetails::Dir(DirectoryRoute {
+        children,
+        path: PathBuf::from(relative_name(
+            &walker.state.destination,
+            &walker.destination,
+        )),
+        feeds: vec![],
+    });
+
+    Route {
+        source: PathBuf::from(relative_name(&walker.state.source, &walker.source)),
+        config: walker.context.config.clone(),
         details: route_details,
-    }))
+    }
+}
+
+// Copy a static asset into the destination. Assets are not routes, so this
+// always returns Ok(None) when it succeeds
+async fn process_asset(walker: Walker, name: OsString, link: bool) -> Result<Option<Route>> {
+    let source = walker.source.join(&name);
+    let path = walker.destination.join(&name);
+    let output = relative_name(&walker.state.destination, &path);
+
+    // an asset depends on nothing but itself
+    let mut inputs = Inputs::new();
+    inputs.insert(
+        relative_name(&walker.state.source, &source),
+        assets::fingerprint(&source).await?,
+    );
+
+    let fresh = walker.options.incremental
+        && walker.state.previous.is_fresh(&output, &inputs)
+        && tokio::fs::try_exists(&path).await.unwrap_or(false);
+
+    if !fresh {
+        assets::publish(&source, &path, link).await?;
+    }
+
+    walker
+        .state
+        .current
+        .lock()
+        .await
+        .outputs
+        .insert(output, inputs);
+
+    Ok(None)
+}
+
+// Copy the assets directory of a theme into the destination. Files the site
+// itself writes there take precedence.
+#[async_recursion]
+async fn process_theme_assets(
+    from: PathBuf,
+    to: PathBuf,
+    state: &Arc<BuildState>,
+    options: &BuildOptions,
+) -> Result<()> {
+    let mut entries = tokio::fs::read_dir(&from)
+        .await
+        .context(format!("Could not read directory `{}`", from.display()))?;
+
+    while let Some(entry) = entries
+        .next_entry()
+        .await
+        .context(format!("Failed to read directory `{}`", from.display()))?
+    {
+        let source = entry.path();
+        let path = to.join(entry.file_name());
+
+        if entry.file_type().await?.is_dir() {
+            process_theme_assets(source, path, state, options).await?;
+            continue;
+        }
+
+        let output = relative_name(&state.destination, &path);
+        if state.current.lock().await.outputs.contains_key(&output) {
+            info!("Keeping `{}` over the theme asset", output);
+            continue;
+        }
+
+        let mut inputs = Inputs::new();
+        inputs.insert(
+            relative_name(&state.source, &source),
+            assets::fingerprint(&source).await?,
+        );
+
+        let fresh = options.incremental
+            && state.previous.is_fresh(&output, &inputs)
+            && tokio::fs::try_exists(&path).await.unwrap_or(false);
+
+        if !fresh {
+            util::dir::create(&to).await;
+            assets::publish(&source, &path, false).await?;
+        }
+
+        state.current.lock().await.outputs.insert(output, inputs);
+    }
+
+    Ok(())
 }
 
 // Returns Ok(None) if the path should be ignored currently (for example
-// if it is a .toml file). Currently ignores everything that is not a .md
+// if it is a .toml file). Only called for .md files.
 #[async_recursion]
 pub async fn process_file(mut walker: Walker, name: OsString) -> Result<Option<Route>> {
     let name = PathBuf::from(name);
 
     let stem = name
         .file_stem()
-        .context(format!("File name cannot be parsed!"))?
+        .context("File name cannot be parsed!")?
         .to_string_lossy()
         .into_owned();
 
-    let file_config = use_path!(walker.source, format!("{}.toml", stem); path => {
-        util::toml::read(&path).await
-    })?;
+    let config_path = walker.source.join(format!("{}.toml", stem));
+    let (file_config, config_contents) = util::toml::read(&config_path).await?;
+    walker.add_input(&config_path, &config_contents);
 
-    let content = use_path!(walker.source, name; path => {
-        util::paths::read(&path).await?
-    });
+    let content_path = walker.source.join(&name);
+    let raw_content = util::paths::read(&content_path).await?;
+    walker.add_input(&content_path, &raw_content);
 
-    // Update old context with new config
-    let context = walker.context.merge_toml(file_config).await?;
+    // Front matter in the markdown file takes precedence over the sibling toml
+    let (front_matter, content) = util::markdown::split_front_matter(&raw_content)
+        .context(format!("In file `{}`.", content_path.display()))?;
+    let content = content.to_owned();
+    let file_config = util::toml::merge(file_config, front_matter)?;
 
-    // use new context to produce the route
-    let route = context.file_route_from_content(content).await?;
+    // Update old context with new config
+    let context = walker
+        .context
+        .clone()
+        .merge_toml(file_config.clone())
+        .await?;
+
+    if !is_published(&context.config, &walker.options)? {
+        info!("Skipping unpublished file `{}`", content_path.display());
+        return Ok(None);
+    }
 
-    // Write to file
-    use_path!(walker.destination, format!("{}.html", stem); path => {
-        tokio::fs::write(&path, &route.html)
-            .await
-            .context(format!(
-                "Failed to write to path `{}`.",
-                path.display()
-            ))?
-    });
+    walker.add_theme_inputs(&context).await?;
+
+    // site::is_pretty ignores a key of the wrong type, so it is caught here
+    assert_toml_kind!(Boolean; context.config.rest, PRETTY_URLS_KEY)?;
+    let dir = PathBuf::from(relative_name(
+        &walker.state.destination,
+        &walker.destination,
+    ));
+    let path = permalink::output_path(&dir, &stem, &file_config, &context.config.rest)
+        .context(format!("In file `{}`.", content_path.display()))?;
+
+    // the page is rendered once the whole tree is known, see walker::render
+    let route = FileRoute {
+        content,
+        html: String::new(),
+        content_html: String::new(),
+        path,
+        inputs: walker.inputs,
+        later_pages: vec![],
+        data: serde_json::Map::new(),
+        aliases: aliases::from_toml(&file_config)?,
+    };
 
     Ok(Some(Route {
+        source: PathBuf::from(relative_name(&walker.state.source, &content_path)),
         config: context.config,
         details: RouteDetails::File(route),
     }))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn graph(outputs: &[&str]) -> DependencyGraph {
+        DependencyGraph {
+            outputs: outputs
+                .iter()
+                .map(|output| (output.to_string(), Inputs::new()))
+                .collect(),
+        }
+    }
+
+    fn published(rest: &str, drafts: bool, future: bool) -> Result<bool> {
+        let config = RouteConfig {
+            rest: toml::from_str(rest).unwrap(),
+            ..RouteConfig::default()
+        };
+        let options = BuildOptions {
+            drafts,
+            future,
+            ..BuildOptions::default()
+        };
+        is_published(&config, &options)
+    }
+
+    #[test]
+    fn drafts_and_future_pages() {
+        let tomorrow = (chrono::Local::now() + chrono::Duration::days(1)).format("%Y-%m-%d");
+        let future = format!(r#"publish_date = "{}""#, tomorrow);
+
+        assert!(published("", false, false).unwrap());
+        assert!(published("draft = false", false, false).unwrap());
+        assert!(!published("draft = true", false, false).unwrap());
+        assert!(published("draft = true", true, false).unwrap());
+
+        assert!(!published(&future, false, false).unwrap());
+        assert!(published(&future, false, true).unwrap());
+        // both flags are needed for a future draft
+        let both = format!("draft = true\n{}", future);
+        assert!(!published(&both, true, false).unwrap());
+        assert!(!published(&both, false, true).unwrap());
+        assert!(published(&both, true, true).unwrap());
+
+        assert!(published("publish_date = 2020-01-01", false, false).unwrap());
+        assert!(published(r#"publish_date = "2020-01-01 10:00""#, false, false).unwrap());
+    }
+
+    #[test]
+    fn bad_draft_keys() {
+        assert!(published(r#"draft = "yes""#, true, true).is_err());
+        assert!(published(r#"publish_date = "soon""#, true, true).is_err());
+    }
+
+    #[test]
+    fn scratch_paths_are_siblings() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = util::paths::resolve(dir.path()).unwrap();
+        std::fs::create_dir(root.join("site")).unwrap();
+
+        let [staging, old, deps] = scratch_paths(&root.join("site/../site/./build")).unwrap();
+        assert_eq!(staging, root.join("site/.build.staging"));
+        assert_eq!(old, root.join("site/.build.old"));
+        assert_eq!(deps, root.join("site/.build.deps.toml"));
+
+        // through symlinks to where they point
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(root.join("site"), root.join("link")).unwrap();
+            let [staging, ..] = scratch_paths(&root.join("link/build")).unwrap();
+            assert_eq!(staging, root.join("site/.build.staging"));
+        }
+    }
+
+    #[test]
+    fn destinations_need_a_name() {
+        for destination in [".", "..", "/", "build/..", "build/../.."] {
+            assert!(
+                scratch_paths(Path::new(destination)).is_err(),
+                "`{}` was accepted",
+                destination
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn prune_only_deletes_stale_outputs() {
+        let dir = tempfile::tempdir().unwrap();
+        let destination = dir.path().join("build");
+        for file in [
+            "a.html",
+            "b.html",
+            "old/c.html",
+            "kept/d.html",
+            "kept/e.png",
+            ".git/HEAD",
+            "CNAME",
+        ] {
+            let path = destination.join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "").unwrap();
+        }
+        std::fs::write(dir.path().join("outside.html"), "").unwrap();
+
+        let previous = graph(&[
+            "a.html",
+            "b.html",
+            "old/c.html",
+            "kept/d.html",
+            "gone.html",
+            "../outside.html",
+            dir.path().join("outside.html").to_str().unwrap(),
+        ]);
+        let current = graph(&["b.html"]);
+        prune(&destination, &previous, &current).await.unwrap();
+
+        let exists = |path: &str| destination.join(path).exists();
+        assert!(!exists("a.html"));
+        assert!(exists("b.html"));
+        // the directories left empty go too
+        assert!(!exists("old"));
+        assert!(!exists("kept/d.html"));
+        assert!(exists("kept/e.png"));
+        // files that ferne did not produce, and anything outside the destination
+        assert!(exists(".git/HEAD"));
+        assert!(exists("CNAME"));
+        assert!(dir.path().join("outside.html").exists());
+        assert!(destination.exists());
+    }
+}
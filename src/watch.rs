@@ -0,0 +1,95 @@
+// Watch the source directory for changes, and report them in batches
+// so that the caller can rebuild once per burst of edits.
+
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{Context, Result};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use tokio::sync::mpsc;
+
+// how long to wait for more events before reporting a batch
+const DEBOUNCE: Duration = Duration::from_millis(150);
+
+pub struct SourceWatcher {
+    _watcher: RecommendedWatcher, // dropping this stops the watch
+    events: mpsc::UnboundedReceiver<notify::Result<Event>>,
+    ignored: Vec<PathBuf>, // changes under these paths are not reported
+}
+
+#[allow(clippy::needless_arbitrary_self_type)]
+impl SourceWatcher {
+    pub fn new(source: &Path) -> Result<Self> {
+        // watch the canonical path, so that the reported paths are canonical too
+        let source = source
+            .canonicalize()
+            .context(format!("Failed to resolve path `{}`.", source.display()))?;
+
+        let (send, events) = mpsc::unbounded_channel();
+
+        let mut watcher = notify::recommended_watcher(move |event| {
+            let _ = send.send(event); // receiver gone means we are shutting down
+        })
+        .context("Failed to create file watcher!")?;
+
+        watcher
+            .watch(&source, RecursiveMode::Recursive)
+            .context(format!("Failed to watch `{}`.", source.display()))?;
+
+        Ok(SourceWatcher {
+            _watcher: watcher,
+            events,
+            ignored: vec![],
+        })
+    }
+
+    // Do not report changes under this path, for example when the destination
+    // lives inside the source. The path itself need not exist, but its parent must.
+    pub fn ignore(self: &mut Self, path: &Path) {
+        if let Ok(path_) = path.canonicalize() {
+            self.ignored.push(path_);
+        } else if let (Some(parent), Some(name)) = (path.parent(), path.file_name()) {
+            let parent = if parent.as_os_str().is_empty() {
+                PathBuf::from(".")
+            } else {
+                parent.to_path_buf()
+            };
+            if let Ok(parent_) = parent.canonicalize() {
+                self.ignored.push(parent_.join(name));
+            }
+        }
+    }
+
+    // Wait for the next batch of changed paths. Returns None if the watcher stopped.
+    pub async fn changes(self: &mut Self) -> Option<Vec<PathBuf>> {
+        let mut changed = vec![];
+
+        loop {
+            let event = if changed.is_empty() {
+                self.events.recv().await?
+            } else {
+                match tokio::time::timeout(DEBOUNCE, self.events.recv()).await {
+                    Ok(Some(event)) => event,
+                    Ok(None) | Err(_) => return Some(changed), // quiet for long enough
+                }
+            };
+
+            let Ok(event) = event else {
+                continue; // errors from the watcher are not actionable here
+            };
+
+            if let EventKind::Access(_) = event.kind {
+                continue;
+            }
+
+            for path in event.paths {
+                let ignored = self.ignored.iter().any(|prefix| path.starts_with(prefix));
+                if !ignored && !changed.contains(&path) {
+                    changed.push(path);
+                }
+            }
+        }
+    }
+}
@@ -6,6 +6,7 @@
 mod loaders;
 mod resource;
 mod resource_path;
+#[allow(clippy::module_inception)]
 mod worker;
 
 pub use worker::*;
@@ -23,6 +23,7 @@ struct Inner<T> {
     cell: OnceCell<T>,
 }
 
+#[allow(clippy::needless_arbitrary_self_type)]
 impl<T> Resource<T> {
     pub fn new(f: impl Fn() -> BoxFuture<T> + Send + Sync + 'static) -> Resource<T> {
         Resource {
@@ -4,6 +4,7 @@ use once_cell::sync::Lazy;
 use regex::Regex;
 
 #[derive(Hash, Eq, PartialEq, Clone, Debug)]
+#[allow(clippy::upper_case_acronyms)]
 pub enum ResourcePath {
     Local(PathBuf),
     URL(String),
@@ -1,11 +1,15 @@
-use std::{collections::HashMap, path::PathBuf, sync::Arc};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
 use anyhow::{Context, Result};
 
 use tokio::sync::{mpsc, oneshot, RwLock};
 use tracing::info;
 
-use super::{loaders, resource::Resource};
+use super::{loaders, resource::Resource, resource_path::ResourcePath};
 
 pub type LoadResponse = Resource<String>;
 
@@ -14,17 +18,24 @@ struct LoadTask {
     chan: oneshot::Sender<LoadResponse>,
 }
 
+enum Task {
+    Load(LoadTask),
+    // drop the cached entries backed by any of these (absolute) local paths
+    Invalidate(Vec<PathBuf>),
+}
+
 type FileIndex = Arc<RwLock<HashMap<String, LoadResponse>>>;
 
 pub struct Worker {
     source: PathBuf,
-    queue: mpsc::Receiver<LoadTask>,
+    queue: mpsc::Receiver<Task>,
     files: FileIndex,
 }
 
 #[derive(Clone, Debug)]
-pub struct SubmitQueue(mpsc::Sender<LoadTask>);
+pub struct SubmitQueue(mpsc::Sender<Task>);
 
+#[allow(clippy::needless_arbitrary_self_type)]
 impl SubmitQueue {
     // panic if submit fails,
     // does not make sense to propagate handling this error into the caller
@@ -37,13 +48,22 @@ impl SubmitQueue {
         let task = LoadTask { path, chan: send };
 
         self.0
-            .send(task)
+            .send(Task::Load(task))
             .await
             .context("Failed to send task to worker")?;
 
         recv.await
             .context("Failed to receive response from worker!")
     }
+
+    // Forget the cached resources loaded from any of the given local paths,
+    // so that the next submit for them loads them afresh
+    pub async fn invalidate(self: Self, paths: Vec<PathBuf>) -> Result<()> {
+        self.0
+            .send(Task::Invalidate(paths))
+            .await
+            .context("Failed to send task to worker")
+    }
 }
 
 impl Worker {
@@ -68,7 +88,7 @@ impl Worker {
         } = self;
 
         loop {
-            let LoadTask { path, chan } = {
+            let task = {
                 let task = queue.recv().await;
                 if task.is_none() {
                     // channel closed, no more work left
@@ -77,6 +97,14 @@ impl Worker {
                 task.unwrap()
             };
 
+            let LoadTask { path, chan } = match task {
+                Task::Load(load_task) => load_task,
+                Task::Invalidate(paths) => {
+                    invalidate(&source, &paths, &files).await;
+                    continue;
+                }
+            };
+
             let files_ = files.clone();
             let source_ = source.clone();
 
@@ -113,3 +141,23 @@ async fn process_single(source: PathBuf, path: String, files: FileIndex) -> Load
 
     // otherwise files_read is dropped here anyway
 }
+
+async fn invalidate(source: &Path, paths: &[PathBuf], files: &FileIndex) {
+    // the changed paths are absolute and canonical, so compare against the canonical source
+    let source = source.canonicalize().unwrap_or(source.to_path_buf());
+
+    let mut files_write = files.write().await;
+    files_write.retain(|key, _| {
+        if let ResourcePath::Local(path) = ResourcePath::from(key.clone()) {
+            let full = source.join(path);
+            let full = full.canonicalize().unwrap_or(full);
+            let stale = paths.iter().any(|changed| changed == &full);
+            if stale {
+                info!("Invalidating cached resource {}", key);
+            }
+            !stale
+        } else {
+            true
+        }
+    });
+}